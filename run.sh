@@ -8,6 +8,6 @@ cargo test
 
 docker build -t compilerbook .
 
-docker run --rm compilerbook cargo run --release 'a = (((4 + 3) / 7 + 4) * (4 - 2) == 10 > 0) * 120;'
-docker run --rm compilerbook cargo run --release '23 * 8+5/ 3 + >'
-docker run --rm compilerbook cargo run --release '((4 > 3) * 5 - 4 * 5'
+docker run --rm compilerbook sh -c "echo 'a = (((4 + 3) / 7 + 4) * (4 - 2) == 10 > 0) * 120;' | cargo run --release -- -S -"
+docker run --rm compilerbook sh -c "echo '23 * 8+5/ 3 + >' | cargo run --release -- -S -"
+docker run --rm compilerbook sh -c "echo '((4 > 3) * 5 - 4 * 5' | cargo run --release -- -S -"
@@ -1,48 +1,40 @@
-#![allow(dead_code)]
+use compiler_book::driver::*;
 use std::env;
-// use std::io::Write;
 use std::process;
-// use std::process::Command;
-use compiler_book::error::*;
-use compiler_book::generator::*;
-use compiler_book::lexer::*;
-use compiler_book::parser::*;
-use compiler_book::static_check::*;
 
-// read compiler book until step10: local variable
 fn main() {
-    // read program
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("invalid number of arguments.");
-        process::exit(1);
-    }
+    // read arguments
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Ok(Command::Compile(options)) => options,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
 
-    // tokenize program
-    let token_stream = TokenStream::tokenize(args[1].clone());
-    match token_stream {
-        Ok(_) => {}
-        Err((message, byte)) => {
-            error_at(args[1].clone(), byte, message);
-            panic!()
+    // read program
+    let program = match read_input(&options.input) {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("error: cannot read {}: {}", options.input, error);
+            process::exit(1);
         }
-    }
+    };
 
-    // enforce static check to tokinized stream
-    if let Err((message, position)) = verify_stream(&token_stream.clone().unwrap()) {
-        error_position(position, &token_stream.unwrap(), args[1].clone(), message);
-        panic!()
-    }
+    // compile program into assembly
+    let assembly = match compile(&program, &options) {
+        Some(assembly) => assembly,
+        None => process::exit(1),
+    };
 
-    // parse stream
-    let ast = parser(&mut token_stream.clone().unwrap());
-    if let Err((message, position)) = ast {
-        error_position(position, &token_stream.unwrap(), args[1].clone(), message);
-        panic!()
+    // write assembly, or assemble and link it
+    if let Err(message) = emit(&assembly, &options) {
+        eprintln!("error: {}", message);
+        process::exit(1);
     }
-
-    // generate assembly program
-    let program = generate_program03(&ast.unwrap());
-
-    println!("{}", program);
 }
@@ -0,0 +1,668 @@
+use crate::error::*;
+use crate::generator::*;
+use crate::lexer::*;
+use crate::parser::*;
+use crate::semantic_check::*;
+use crate::type_check::*;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const USAGE: &str = "\
+usage: compiler-book [options] <input>
+
+arguments:
+   <input>       C source file to compile, `-` reads from stdin
+
+options:
+   -S            only compile into assembly
+   -c            compile and assemble into an object file
+   -o <output>   write output to <output>, `-` writes assembly to stdout
+   -ferror-limit=<n>
+                 stop after <n> errors, 0 for no limit (default: 20)
+   -w            suppress all warnings
+   -Werror       treat warnings as errors
+   -Wall         enable all warnings (default)
+   -W<warning>, -Wno-<warning>
+                 enable or disable uninitialized, unused-but-set-variable,
+                 tautological-compare or unused-variable
+   --error-format=<human|json>
+                 print diagnostics with source lines or as one JSON object
+                 per line (default: human)
+   -h, --help    print this message
+
+without -S or -c the object is linked into an executable (default: a.out).
+";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Command {
+    Help,
+    Compile(Options),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Mode {
+    // -S
+    Assembly,
+    // -c
+    Object,
+    Executable,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ErrorFormat {
+    // caret under the source line
+    Human,
+    // one object per line for editors and CI
+    Json,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Warnings {
+    // cleared by -w
+    pub enabled: bool,
+    // -Werror
+    pub as_errors: bool,
+    // -Wno-<warning>
+    pub disabled: Vec<Code>,
+}
+
+impl Default for Warnings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            as_errors: false,
+            disabled: Vec::new(),
+        }
+    }
+}
+
+impl Warnings {
+    // drop disabled warnings and turn the rest into errors for -Werror
+    pub fn filter(&self, warnings: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        warnings
+            .into_iter()
+            .filter(|warning| self.enabled && !self.disabled.contains(&warning.code))
+            .map(|mut warning| {
+                if self.as_errors {
+                    warning.severity = Severity::Error;
+                }
+                warning
+            })
+            .collect()
+    }
+
+    // `-W...` except for -w
+    fn parse(&mut self, option: &str) -> Result<(), String> {
+        let find = |name: &str| {
+            Code::WARNINGS
+                .iter()
+                .copied()
+                .find(|code| code.warning_name() == Some(name))
+                .ok_or_else(|| format!("unknown warning option `{}`.", option))
+        };
+        match &option[2..] {
+            "error" => self.as_errors = true,
+            "all" => {
+                self.enabled = true;
+                self.disabled.clear();
+            }
+            name if name.starts_with("no-") => {
+                let code = find(&name[3..])?;
+                self.disabled.push(code);
+            }
+            name => {
+                let code = find(name)?;
+                self.enabled = true;
+                self.disabled.retain(|&disabled| disabled != code);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Options {
+    pub input: String,
+    // `None` picks the default name for the mode
+    pub output: Option<String>,
+    pub mode: Mode,
+    // number of errors to print, 0 prints all of them
+    pub error_limit: usize,
+    pub error_format: ErrorFormat,
+    pub warnings: Warnings,
+}
+
+impl Options {
+    pub fn new(input: &str) -> Self {
+        Self {
+            input: input.to_string(),
+            output: None,
+            mode: Mode::Executable,
+            error_limit: 20,
+            error_format: ErrorFormat::Human,
+            warnings: Warnings::default(),
+        }
+    }
+
+    // the file to write, `None` writes assembly to stdout
+    pub fn output_path(&self) -> Option<PathBuf> {
+        if let Some(output) = &self.output {
+            return if output == "-" && self.mode == Mode::Assembly {
+                None
+            } else {
+                Some(PathBuf::from(output))
+            };
+        }
+        let stem = Path::new(&self.input)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned());
+        match (self.mode, stem) {
+            (Mode::Executable, _) => Some(PathBuf::from("a.out")),
+            // `-S` for stdin writes to stdout
+            (Mode::Assembly, _) if self.input == "-" => None,
+            (Mode::Assembly, Some(stem)) => Some(PathBuf::from(format!("{}.s", stem))),
+            (Mode::Object, Some(stem)) if self.input != "-" => {
+                Some(PathBuf::from(format!("{}.o", stem)))
+            }
+            (_, _) => Some(PathBuf::from("a.o")),
+        }
+    }
+}
+
+// private directory for intermediate files removed on drop
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> io::Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0);
+        let mut attempt = 0;
+        loop {
+            let path = env::temp_dir().join(format!(
+                "compiler-book-{}-{}-{}",
+                process::id(),
+                nanos,
+                attempt
+            ));
+            let mut builder = fs::DirBuilder::new();
+            #[cfg(unix)]
+            std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+            match builder.create(&path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => {
+                    attempt += 1
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// parse arguments without the program name
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut mode = Mode::Executable;
+    let mut error_limit = Options::new("").error_limit;
+    let mut error_format = Options::new("").error_format;
+    let mut warnings = Warnings::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-S" => mode = Mode::Assembly,
+            "-c" if mode != Mode::Assembly => mode = Mode::Object,
+            "-c" => {}
+            "-o" => match args.next() {
+                Some(file) => output = Some(file.clone()),
+                None => return Err("missing file name after `-o`.".to_string()),
+            },
+            _ if arg.starts_with("-o") => output = Some(arg[2..].to_string()),
+            _ if arg.starts_with("-ferror-limit=") => {
+                let limit = &arg["-ferror-limit=".len()..];
+                error_limit = limit
+                    .parse()
+                    .map_err(|_| format!("invalid error limit `{}`.", limit))?
+            }
+            "-w" => warnings.enabled = false,
+            _ if arg.starts_with("-W") => warnings.parse(arg)?,
+            "--error-format=human" => error_format = ErrorFormat::Human,
+            "--error-format=json" => error_format = ErrorFormat::Json,
+            _ if arg.starts_with("--error-format=") => {
+                return Err(format!(
+                    "unknown error format `{}`.",
+                    &arg["--error-format=".len()..]
+                ))
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option `{}`.", arg))
+            }
+            _ if input.is_some() => return Err("only one input file is allowed.".to_string()),
+            _ => input = Some(arg.clone()),
+        }
+    }
+    let input = input.ok_or_else(|| "no input file.".to_string())?;
+    // only assembly can be written to stdout
+    if output.as_deref() == Some("-") && mode != Mode::Assembly {
+        return Err("cannot write an object or an executable to stdout.".to_string());
+    }
+    Ok(Command::Compile(Options {
+        input,
+        output,
+        mode,
+        error_limit,
+        error_format,
+        warnings,
+    }))
+}
+
+pub fn read_input(input: &str) -> io::Result<String> {
+    if input == "-" {
+        let mut program = String::new();
+        io::stdin().read_to_string(&mut program)?;
+        Ok(program)
+    } else {
+        fs::read_to_string(input)
+    }
+}
+
+// write assembly, an object or an executable as requested by options
+pub fn emit(assembly: &str, options: &Options) -> Result<(), String> {
+    let output = options.output_path();
+    let output = match (options.mode, output) {
+        (_, None) => {
+            return io::stdout()
+                .write_all(assembly.as_bytes())
+                .map_err(|error| format!("cannot write stdout: {}", error))
+        }
+        (Mode::Assembly, Some(output)) => {
+            return fs::write(&output, assembly)
+                .map_err(|error| format!("cannot write {}: {}", output.display(), error))
+        }
+        (_, Some(output)) => output,
+    };
+
+    let temp = TempDir::new().map_err(|error| format!("cannot create temp dir: {}", error))?;
+    let source = temp.path().join("out.s");
+    fs::write(&source, assembly)
+        .map_err(|error| format!("cannot write {}: {}", source.display(), error))?;
+    if options.mode == Mode::Object {
+        return assemble(&source, &output);
+    }
+    let object = temp.path().join("out.o");
+    assemble(&source, &object)?;
+    link(&object, &output)
+}
+
+// invoke the system assembler
+pub fn assemble(source: &Path, object: &Path) -> Result<(), String> {
+    run(
+        process::Command::new("as")
+            .arg("-o")
+            .arg(object)
+            .arg(source),
+        "as",
+    )
+}
+
+// link with libc through the system compiler driver
+pub fn link(object: &Path, executable: &Path) -> Result<(), String> {
+    run(
+        process::Command::new("cc")
+            .arg("-o")
+            .arg(executable)
+            .arg(object),
+        "cc",
+    )
+}
+
+fn run(command: &mut process::Command, name: &str) -> Result<(), String> {
+    let status = command
+        .status()
+        .map_err(|error| format!("cannot run {}: {}", name, error))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} failed with {}", name, status))
+    }
+}
+
+// name of the input used in diagnostics
+pub fn input_name(input: &str) -> &str {
+    if input == "-" {
+        "<stdin>"
+    } else {
+        input
+    }
+}
+
+// compile a program to assembly, printing errors on failure
+pub fn compile(program: &str, options: &Options) -> Option<String> {
+    // tokenize program, locations follow the line markers it skips
+    let mut markers = Vec::new();
+    let tokenized = TokenStream::tokenize_with_markers(program.to_string(), &mut markers);
+    let source = &SourceMap::new(input_name(&options.input), program).with_markers(markers);
+    let mut stream = match tokenized {
+        Ok(stream) => stream,
+        Err(error) => {
+            report(&[error], source, options);
+            return None;
+        }
+    };
+
+    // parse stream, reporting every broken statement at once
+    let mut ast = match parser(&mut stream) {
+        Ok(ast) => ast,
+        Err(errors) => {
+            report(&errors, source, options);
+            return None;
+        }
+    };
+
+    // give every expression a type and make conversions explicit
+    if let Err(errors) = check_types(&mut ast) {
+        report(&errors, source, options);
+        return None;
+    }
+
+    // warn about suspicious code, which fails to compile with -Werror
+    let warnings = options.warnings.filter(check_program(&ast));
+    report(&warnings, source, options);
+    if options.warnings.as_errors && !warnings.is_empty() {
+        return None;
+    }
+
+    // generate assembly program
+    Some(generate_program03(&ast))
+}
+
+// how many diagnostics are printed before the limit of errors is reached,
+// 0 means no limit and warnings are never counted
+fn shown(diagnostics: &[Diagnostic], limit: usize) -> usize {
+    let mut errors = 0;
+    for (index, diagnostic) in diagnostics.iter().enumerate() {
+        if diagnostic.severity == Severity::Error {
+            if limit != 0 && errors == limit {
+                return index;
+            }
+            errors += 1;
+        }
+    }
+    diagnostics.len()
+}
+
+fn report(diagnostics: &[Diagnostic], source: &SourceMap, options: &Options) {
+    let count = shown(diagnostics, options.error_limit);
+    for diagnostic in &diagnostics[..count] {
+        match options.error_format {
+            ErrorFormat::Human => print_diagnostic(source, diagnostic),
+            ErrorFormat::Json => eprintln!("{}", source.render_json(diagnostic)),
+        }
+    }
+    // keep every line of JSON an object
+    if count < diagnostics.len() && options.error_format == ErrorFormat::Human {
+        eprintln!("fatal error: too many errors emitted, stopping now [-ferror-limit=]");
+    }
+}
+
+#[cfg(test)]
+mod tests_driver {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn for_parse_args() {
+        let cases = vec![
+            vec!["input.c"],
+            vec!["-S", "input.c", "-o", "out.s"],
+            vec!["-oout", "input.c"],
+            vec!["-", "-S", "-o", "-"],
+            vec!["-c", "-S", "input.c"],
+            vec!["-ferror-limit=0", "input.c"],
+            vec!["--error-format=json", "input.c"],
+            vec![
+                "-Werror",
+                "-Wno-uninitialized",
+                "-Wtautological-compare",
+                "input.c",
+            ],
+            vec!["-Wno-tautological-compare", "-w", "-Wall", "input.c"],
+            vec!["input.c", "--help"],
+        ];
+        let answers = vec![
+            Command::Compile(Options::new("input.c")),
+            Command::Compile(Options {
+                output: Some("out.s".to_string()),
+                mode: Mode::Assembly,
+                ..Options::new("input.c")
+            }),
+            Command::Compile(Options {
+                output: Some("out".to_string()),
+                ..Options::new("input.c")
+            }),
+            Command::Compile(Options {
+                output: Some("-".to_string()),
+                mode: Mode::Assembly,
+                ..Options::new("-")
+            }),
+            Command::Compile(Options {
+                mode: Mode::Assembly,
+                ..Options::new("input.c")
+            }),
+            Command::Compile(Options {
+                error_limit: 0,
+                ..Options::new("input.c")
+            }),
+            Command::Compile(Options {
+                error_format: ErrorFormat::Json,
+                ..Options::new("input.c")
+            }),
+            Command::Compile(Options {
+                warnings: Warnings {
+                    enabled: true,
+                    as_errors: true,
+                    disabled: vec![Code::Uninitialized],
+                },
+                ..Options::new("input.c")
+            }),
+            Command::Compile(Options::new("input.c")),
+            Command::Help,
+        ];
+        for (case, answer) in cases.into_iter().zip(answers) {
+            assert_eq!(parse_args(&args(&case)), Ok(answer));
+        }
+    }
+
+    #[test]
+    fn for_parse_args_error() {
+        let cases = vec![
+            vec![],
+            vec!["input.c", "-o"],
+            vec!["a.c", "b.c"],
+            vec!["-x", "a.c"],
+            vec!["-ferror-limit=x", "a.c"],
+            vec!["--error-format=xml", "a.c"],
+            vec!["-Wno-unused", "a.c"],
+            vec!["-c", "-o", "-", "a.c"],
+            vec!["-o-", "a.c"],
+        ];
+        let errors = vec![
+            "no input file.",
+            "missing file name after `-o`.",
+            "only one input file is allowed.",
+            "unknown option `-x`.",
+            "invalid error limit `x`.",
+            "unknown error format `xml`.",
+            "unknown warning option `-Wno-unused`.",
+            "cannot write an object or an executable to stdout.",
+            "cannot write an object or an executable to stdout.",
+        ];
+        for (case, error) in cases.into_iter().zip(errors) {
+            assert_eq!(parse_args(&args(&case)), Err(error.to_string()));
+        }
+    }
+
+    #[test]
+    fn for_output_path() {
+        let cases = vec![
+            ("dir/input.c", None, Mode::Assembly),
+            ("dir/input.c", None, Mode::Object),
+            ("dir/input.c", None, Mode::Executable),
+            ("-", None, Mode::Assembly),
+            ("-", None, Mode::Object),
+            ("input.c", Some("-"), Mode::Assembly),
+            ("input.c", Some("out"), Mode::Executable),
+        ];
+        let answers = vec![
+            Some("input.s"),
+            Some("input.o"),
+            Some("a.out"),
+            None,
+            Some("a.o"),
+            None,
+            Some("out"),
+        ];
+        for ((input, output, mode), answer) in cases.into_iter().zip(answers) {
+            let options = Options {
+                output: output.map(|output| output.to_string()),
+                mode,
+                ..Options::new(input)
+            };
+            assert_eq!(options.output_path(), answer.map(PathBuf::from));
+        }
+    }
+
+    #[test]
+    fn for_temp_dir() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().to_path_buf();
+        fs::write(path.join("file"), "").unwrap();
+        assert!(path.is_dir());
+        drop(temp);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn for_emit() {
+        let temp = TempDir::new().unwrap();
+        let assembly = compile("int main() { return 42; }", &Options::new("input.c")).unwrap();
+        let cases = vec![
+            (Mode::Assembly, "out.s"),
+            (Mode::Object, "out.o"),
+            (Mode::Executable, "out"),
+        ];
+        for (mode, output) in cases {
+            let output = temp.path().join(output);
+            let options = Options {
+                output: Some(output.to_string_lossy().into_owned()),
+                mode,
+                ..Options::new("input.c")
+            };
+            emit(&assembly, &options).unwrap();
+            assert!(output.is_file());
+        }
+        assert_eq!(
+            fs::read_to_string(temp.path().join("out.s")).unwrap(),
+            assembly
+        );
+        let status = process::Command::new(temp.path().join("out"))
+            .status()
+            .unwrap();
+        assert_eq!(status.code(), Some(42));
+    }
+
+    #[test]
+    fn for_input_name() {
+        assert_eq!(input_name("-"), "<stdin>");
+        assert_eq!(input_name("dir/input.c"), "dir/input.c");
+    }
+
+    #[test]
+    fn for_compile() {
+        let cases = vec![
+            ("int main() { return 0; }", true),
+            ("int main() { return 0 }", false),
+            ("☀︎", false),
+        ];
+        for (case, answer) in cases {
+            assert_eq!(compile(case, &Options::new("input.c")).is_some(), answer);
+        }
+    }
+
+    #[test]
+    fn for_error_limit() {
+        let error = Diagnostic::error(Code::NeedNumber, "", Span::at(0));
+        let warning = Diagnostic::warning(Code::Uninitialized, "", Span::at(0));
+        let diagnostics = vec![
+            warning.clone(),
+            error.clone(),
+            warning.clone(),
+            error.clone(),
+            error,
+        ];
+        assert_eq!(shown(&diagnostics, 0), 5);
+        assert_eq!(shown(&diagnostics, 1), 3);
+        assert_eq!(shown(&diagnostics, 2), 4);
+        assert_eq!(shown(&diagnostics, 3), 5);
+        assert_eq!(shown(&[warning.clone(), warning], 1), 2);
+    }
+
+    #[test]
+    fn for_warnings() {
+        let source = "int main() { int x; return x; }";
+        let cases = vec![
+            (vec!["input.c"], true),
+            (vec!["-Werror", "input.c"], false),
+            (vec!["-Werror", "-w", "input.c"], true),
+            (vec!["-Werror", "-Wno-uninitialized", "input.c"], true),
+        ];
+        for (case, answer) in cases {
+            let options = match parse_args(&args(&case)).unwrap() {
+                Command::Compile(options) => options,
+                Command::Help => unreachable!(),
+            };
+            assert_eq!(compile(source, &options).is_some(), answer);
+        }
+        let source = "int main() { int x; return 0; }";
+        let cases = vec![
+            (vec!["-Werror", "input.c"], false),
+            (vec!["-Werror", "-Wno-unused-variable", "input.c"], true),
+        ];
+        for (case, answer) in cases {
+            let options = match parse_args(&args(&case)).unwrap() {
+                Command::Compile(options) => options,
+                Command::Help => unreachable!(),
+            };
+            assert_eq!(compile(source, &options).is_some(), answer);
+        }
+
+        let warning = Diagnostic::warning(Code::Uninitialized, "", Span::at(0));
+        let warnings = Warnings {
+            as_errors: true,
+            ..Warnings::default()
+        };
+        assert_eq!(warnings.filter(vec![warning])[0].severity, Severity::Error);
+    }
+}
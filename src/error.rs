@@ -1,32 +1,634 @@
-use crate::lexer::*;
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Byte(pub usize);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Position(pub usize);
 
-pub fn error_position(position: Position, stream: &TokenStream, program: String, message: String) {
-    let Position(index) = position;
-    error_at(
-        program,
-        Byte(
-            *stream
-                .position
-                .iter()
-                .nth(index)
-                .expect("inner error: out of bound"),
-        ),
-        message,
-    );
-}
-
-pub fn error_at(program: String, byte: Byte, message: String) {
-    println!("\nError:\n   {}", program);
-    let Byte(index) = byte;
-    println!(
-        "   {}^ {}\n",
-        (0..index).map(|_| " ").collect::<String>(),
-        message
-    );
+// bytes `start..end` of the source, `start == end` points at a single place
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn at(byte: usize) -> Self {
+        Self {
+            start: byte,
+            end: byte,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+// stable identifier of each diagnostic, never reuse a retired number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    UnknownCharacter,
+    EmptyInput,
+    NeedNumber,
+    NeedSemicolon,
+    NeedOperator,
+    NeedBracket,
+    NeedStatement,
+    NeedIdentifier,
+    NeedComma,
+    NeedParameter,
+    NeedBrace,
+    NeedType,
+    UnmatchedBracket,
+    UnmatchedBrace,
+    // no longer emitted since `,` is an operator, kept so that codes stay stable
+    MisplacedComma,
+    // no longer emitted since unary operators nest, kept so that codes stay stable
+    RepeatedUnary,
+    NotAssignable,
+    MainConflict,
+    UnterminatedComment,
+    UnterminatedLiteral,
+    InvalidEscape,
+    InvalidCharacterLiteral,
+    InvalidInteger,
+    IntegerOverflow,
+    NeedColon,
+    InvalidType,
+    VoidValue,
+    InvalidOperand,
+    NotConstant,
+    Redefinition,
+    ArgumentCount,
+    ReturnValue,
+    Undeclared,
+    Incompatible,
+    Uninitialized,
+    UnusedButSet,
+    TautologicalCompare,
+    Unused,
+}
+
+impl Code {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Code::UnknownCharacter => "E0001",
+            Code::EmptyInput => "E0002",
+            Code::NeedNumber => "E0003",
+            Code::NeedSemicolon => "E0004",
+            Code::NeedOperator => "E0005",
+            Code::NeedBracket => "E0006",
+            Code::NeedStatement => "E0007",
+            Code::NeedIdentifier => "E0008",
+            Code::NeedComma => "E0009",
+            Code::NeedParameter => "E0010",
+            Code::NeedBrace => "E0011",
+            Code::NeedType => "E0012",
+            Code::UnmatchedBracket => "E0013",
+            Code::UnmatchedBrace => "E0014",
+            Code::MisplacedComma => "E0015",
+            Code::RepeatedUnary => "E0016",
+            Code::NotAssignable => "E0017",
+            Code::MainConflict => "E0018",
+            Code::UnterminatedComment => "E0019",
+            Code::UnterminatedLiteral => "E0020",
+            Code::InvalidEscape => "E0021",
+            Code::InvalidCharacterLiteral => "E0022",
+            Code::InvalidInteger => "E0023",
+            Code::IntegerOverflow => "E0024",
+            Code::NeedColon => "E0025",
+            Code::InvalidType => "E0026",
+            Code::VoidValue => "E0027",
+            Code::InvalidOperand => "E0028",
+            Code::NotConstant => "E0029",
+            Code::Redefinition => "E0030",
+            Code::ArgumentCount => "E0031",
+            Code::ReturnValue => "E0032",
+            Code::Undeclared => "E0033",
+            Code::Incompatible => "E0034",
+            Code::Uninitialized => "W0001",
+            Code::UnusedButSet => "W0002",
+            Code::TautologicalCompare => "W0003",
+            Code::Unused => "W0004",
+        }
+    }
+
+    // name used by `-W<name>` and `-Wno-<name>` to select a warning
+    pub fn warning_name(self) -> Option<&'static str> {
+        match self {
+            Code::Uninitialized => Some("uninitialized"),
+            Code::UnusedButSet => Some("unused-but-set-variable"),
+            Code::TautologicalCompare => Some("tautological-compare"),
+            Code::Unused => Some("unused-variable"),
+            _ => None,
+        }
+    }
+
+    pub const WARNINGS: [Code; 4] = [
+        Code::Uninitialized,
+        Code::UnusedButSet,
+        Code::TautologicalCompare,
+        Code::Unused,
+    ];
+}
+
+// secondary place related to a diagnostic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub code: Code,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    pub labels: Vec<Label>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: Code, message: &str, span: Span) -> Self {
+        Self {
+            code,
+            severity,
+            message: message.to_string(),
+            span,
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn error(code: Code, message: &str, span: Span) -> Self {
+        Self::new(Severity::Error, code, message, span)
+    }
+
+    pub fn warning(code: Code, message: &str, span: Span) -> Self {
+        Self::new(Severity::Warning, code, message, span)
+    }
+
+    pub fn with_label(mut self, span: Span, message: &str) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.to_string(),
+        });
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = Some(help.to_string());
+        self
+    }
+}
+
+// line and column of a byte, both start from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    // counted in characters, not bytes
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMap {
+    pub name: String,
+    pub source: String,
+    // byte offset where each line starts
+    lines: Vec<usize>,
+    markers: Vec<LineMarker>,
+}
+
+// a line marker skipped by the lexer, with the byte where its line ends
+// and the line and file it gives
+pub type Marker = (Byte, usize, Option<String>);
+
+// `# 12 "file.c"` makes the next line be line 12 of file.c
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LineMarker {
+    // line of the source right after the marker
+    line: usize,
+    presumed: usize,
+    file: String,
+}
+
+// line number and file name of `# 12 "file.c"` or `#line 12 "file.c"`,
+// trailing flags written by cpp are ignored
+pub fn line_marker(text: &str) -> Option<(usize, Option<String>)> {
+    let text = text.trim_start().strip_prefix('#')?.trim_start();
+    let text = match text.strip_prefix("line") {
+        Some(rest) if rest.starts_with(char::is_whitespace) => rest.trim_start(),
+        _ => text,
+    };
+    let digits = text.chars().take_while(char::is_ascii_digit).count();
+    let line = text[..digits].parse().ok()?;
+    let rest = &text[digits..];
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let file = match rest.strip_prefix('"') {
+        Some(quoted) => {
+            let mut file = String::new();
+            let mut chars = quoted.chars();
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => file.push(chars.next()?),
+                    c => file.push(c),
+                }
+            }
+            Some(file)
+        }
+        None if rest.is_empty() => None,
+        None => return None,
+    };
+    Some((line, file))
+}
+
+impl SourceMap {
+    pub fn new(name: &str, source: &str) -> Self {
+        let lines: Vec<usize> = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        Self {
+            name: name.to_string(),
+            source: source.to_string(),
+            lines,
+            markers: Vec::new(),
+        }
+    }
+
+    // only the markers the lexer skipped count, a line in a comment which
+    // looks like one does not
+    pub fn with_markers(mut self, markers: Vec<Marker>) -> Self {
+        for (end, presumed, file) in markers {
+            let file = file.unwrap_or_else(|| {
+                self.markers
+                    .last()
+                    .map_or(self.name.clone(), |marker| marker.file.clone())
+            });
+            let line = self.location(end).line + 1;
+            self.markers.push(LineMarker {
+                line,
+                presumed,
+                file,
+            });
+        }
+        self
+    }
+
+    // file and line of a byte as told by the last line marker before it
+    pub fn presumed(&self, byte: Byte) -> (&str, usize) {
+        let line = self.location(byte).line;
+        match self.markers.iter().rev().find(|marker| marker.line <= line) {
+            Some(marker) => (&marker.file, marker.presumed + line - marker.line),
+            None => (&self.name, line),
+        }
+    }
+
+    pub fn location(&self, byte: Byte) -> Location {
+        let index = self.clamp(byte);
+        let line = match self.lines.binary_search(&index) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let column = self.source[self.lines[line]..index].chars().count() + 1;
+        Location {
+            line: line + 1,
+            column,
+        }
+    }
+
+    // text of a line without its newline
+    pub fn line(&self, line: usize) -> &str {
+        let start = self.lines[line - 1];
+        let end = self
+            .lines
+            .get(line)
+            .map(|&next| next - 1)
+            .unwrap_or_else(|| self.source.len());
+        self.source[start..end].trim_end_matches('\r')
+    }
+
+    // keep a byte inside the source on a character boundary; the end of a
+    // file with a trailing newline is reported at the end of its last line
+    fn clamp(&self, Byte(index): Byte) -> usize {
+        let mut index = index.min(self.source.len());
+        if index == self.source.len() && self.source.ends_with('\n') {
+            index -= 1;
+        }
+        while !self.source.is_char_boundary(index) {
+            index -= 1;
+        }
+        index
+    }
+
+    // `file.c:3:7: error[E0004]: message` followed by the line and a caret,
+    // then each label as a note and the help
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut buffer = format!(
+            "{}: {}[{}]: {}",
+            self.place(diagnostic.span),
+            diagnostic.severity.as_str(),
+            diagnostic.code.as_str(),
+            diagnostic.message
+        );
+        // tell how to turn the warning off
+        if let Some(name) = diagnostic.code.warning_name() {
+            buffer.push_str(&format!(" [-W{}]", name));
+        }
+        buffer.push('\n');
+        buffer.push_str(&self.snippet(diagnostic.span));
+        for label in &diagnostic.labels {
+            buffer.push_str(&format!(
+                "{}: {}: {}\n",
+                self.place(label.span),
+                Severity::Note.as_str(),
+                label.message
+            ));
+            buffer.push_str(&self.snippet(label.span));
+        }
+        if let Some(help) = &diagnostic.help {
+            buffer.push_str(&format!("   = help: {}\n", help));
+        }
+        buffer
+    }
+
+    // `file.c:3:7`
+    fn place(&self, span: Span) -> String {
+        let (file, line) = self.presumed(Byte(span.start));
+        let location = self.location(Byte(span.start));
+        format!("{}:{}:{}", file, line, location.column)
+    }
+
+    // the line of a span underlined up to its end or the end of the line
+    fn snippet(&self, span: Span) -> String {
+        let location = self.location(Byte(span.start));
+        let text = self.line(location.line);
+        let number = self.presumed(Byte(span.start)).1.to_string();
+        // reuse tabs of the line so that the carets line up in any terminal
+        let indent = text
+            .chars()
+            .take(location.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let start = self.clamp(Byte(span.start));
+        let end = self
+            .clamp(Byte(span.end))
+            .clamp(start, self.lines[location.line - 1] + text.len());
+        let width = self.source[start..end].chars().count().max(1);
+        format!(
+            " {} | {}\n {} | {}{}\n",
+            number,
+            text,
+            " ".repeat(number.len()),
+            indent,
+            "^".repeat(width)
+        )
+    }
+
+    // one line of JSON for tools, columns count characters from 1
+    pub fn render_json(&self, diagnostic: &Diagnostic) -> String {
+        let labels = diagnostic
+            .labels
+            .iter()
+            .map(|label| {
+                format!(
+                    "{{{},{}}}",
+                    self.json_span(label.span),
+                    json_field("message", &label.message)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let help = match &diagnostic.help {
+            Some(help) => json_string(help),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{{},{},{},{},\"labels\":[{}],\"help\":{}}}",
+            self.json_span(diagnostic.span),
+            json_field("code", diagnostic.code.as_str()),
+            json_field("severity", diagnostic.severity.as_str()),
+            json_field("message", &diagnostic.message),
+            labels,
+            help
+        )
+    }
+
+    fn json_span(&self, span: Span) -> String {
+        let (file, line) = self.presumed(Byte(span.start));
+        let location = self.location(Byte(span.start));
+        format!(
+            "{},\"line\":{},\"column\":{},\"start\":{},\"end\":{}",
+            json_field("file", file),
+            line,
+            location.column,
+            span.start,
+            span.end
+        )
+    }
+}
+
+fn json_field(name: &str, value: &str) -> String {
+    format!("\"{}\":{}", name, json_string(value))
+}
+
+fn json_string(value: &str) -> String {
+    let mut buffer = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => buffer.push_str("\\\""),
+            '\\' => buffer.push_str("\\\\"),
+            '\n' => buffer.push_str("\\n"),
+            '\r' => buffer.push_str("\\r"),
+            '\t' => buffer.push_str("\\t"),
+            c if (c as u32) < 0x20 => buffer.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buffer.push(c),
+        }
+    }
+    buffer.push('"');
+    buffer
+}
+
+// diagnostics are written to stderr so that `-S -o -` keeps stdout clean
+pub fn print_diagnostic(source: &SourceMap, diagnostic: &Diagnostic) {
+    eprint!("{}", source.render(diagnostic));
+}
+
+#[cfg(test)]
+mod tests_error {
+    use super::*;
+    use crate::lexer::TokenStream;
+
+    #[test]
+    fn for_location() {
+        let source = SourceMap::new("test.c", "int main() {\n\treturn 42;\n}\n");
+        let cases = vec![0, 4, 13, 14, 25, 27, 28, 100];
+        let answers = vec![
+            (1, 1),
+            (1, 5),
+            (2, 1),
+            (2, 2),
+            (3, 1),
+            (3, 2),
+            (3, 2),
+            (3, 2),
+        ];
+        for (case, (line, column)) in cases.into_iter().zip(answers) {
+            assert_eq!(source.location(Byte(case)), Location { line, column });
+        }
+        assert_eq!(source.line(2), "\treturn 42;");
+    }
+
+    #[test]
+    fn for_location_multibyte() {
+        // `☀` takes three bytes but one column
+        let source = SourceMap::new("test.c", "a = 1;\n☀ = 2;\r\nb");
+        assert_eq!(source.location(Byte(7)), Location { line: 2, column: 1 });
+        assert_eq!(source.location(Byte(11)), Location { line: 2, column: 3 });
+        // inside of a character points at the character
+        assert_eq!(source.location(Byte(9)), Location { line: 2, column: 1 });
+        assert_eq!(source.location(Byte(17)), Location { line: 3, column: 1 });
+        assert_eq!(source.line(2), "☀ = 2;");
+    }
+
+    #[test]
+    fn for_render() {
+        let source = SourceMap::new("test.c", "int x;\n\tx = ☀ + $;\n");
+        assert_eq!(
+            source.render(&Diagnostic::error(
+                Code::UnknownCharacter,
+                "fail to lex.",
+                Span::at(16)
+            )),
+            "test.c:2:8: error[E0001]: fail to lex.\n 2 | \tx = ☀ + $;\n   | \t      ^\n"
+        );
+        assert_eq!(
+            source.render(
+                &Diagnostic::error(
+                    Code::NeedSemicolon,
+                    "fail to parse: need semicolon here.",
+                    Span::at(18)
+                )
+                .with_label(Span::at(4), "`x` is declared here")
+                .with_help("end the statement with `;`")
+            ),
+            "test.c:2:10: error[E0004]: fail to parse: need semicolon here.\n 2 | \tx = ☀ + $;\n   | \t        ^\n\
+             test.c:1:5: note: `x` is declared here\n 1 | int x;\n   |     ^\n   = help: end the statement with `;`\n"
+        );
+    }
+
+    #[test]
+    fn for_render_underline() {
+        let source = SourceMap::new("test.c", "x = ☀ + foo;\n/* a\nb */");
+        let underline = |start, end| {
+            source
+                .render(&Diagnostic::error(
+                    Code::UnknownCharacter,
+                    "fail.",
+                    Span { start, end },
+                ))
+                .lines()
+                .nth(2)
+                .unwrap()
+                .to_string()
+        };
+        // the sun is one character of three bytes
+        assert_eq!(underline(4, 7), "   |     ^");
+        assert_eq!(underline(10, 13), "   |         ^^^");
+        // cut at the end of the line
+        assert_eq!(underline(15, 24), "   | ^^^^");
+    }
+
+    #[test]
+    fn for_render_json() {
+        let source = SourceMap::new("dir/\"test\".c", "int x;\n\tx = ☀ + $;\n");
+        assert_eq!(
+            source.render_json(
+                &Diagnostic::error(Code::UnknownCharacter, "fail to lex. left: \\\n.", Span::at(16))
+                    .with_label(Span::at(4), "`x` is declared here")
+            ),
+            "{\"file\":\"dir/\\\"test\\\".c\",\"line\":2,\"column\":8,\"start\":16,\"end\":16,\
+             \"code\":\"E0001\",\"severity\":\"error\",\"message\":\"fail to lex. left: \\\\\\n.\",\
+             \"labels\":[{\"file\":\"dir/\\\"test\\\".c\",\"line\":1,\"column\":5,\"start\":4,\"end\":4,\"message\":\"`x` is declared here\"}],\
+             \"help\":null}"
+        );
+    }
+
+    #[test]
+    fn for_line_marker() {
+        let cases = vec![
+            "# 12 \"dir/a.h\"",
+            "  #  3",
+            "#line 7 \"b.c\"",
+            "# 1 \"<built-in>\" 1 3 4",
+            "# 5 \"a\\\"b.c\"",
+            "#include <stdio.h>",
+            "# 12x",
+            "# 3 \"open",
+            "#",
+        ];
+        let answers = vec![
+            Some((12, Some("dir/a.h"))),
+            Some((3, None)),
+            Some((7, Some("b.c"))),
+            Some((1, Some("<built-in>"))),
+            Some((5, Some("a\"b.c"))),
+            None,
+            None,
+            None,
+            None,
+        ];
+        for (case, answer) in cases.into_iter().zip(answers) {
+            assert_eq!(
+                line_marker(case),
+                answer.map(|(line, file)| (line, file.map(|file| file.to_string())))
+            );
+        }
+
+        let source = marked(
+            "main.i",
+            "int x;\n# 10 \"a.h\"\nint y;\nint z;\n# 3\n  $;\n",
+        );
+        assert_eq!(source.presumed(Byte(0)), ("main.i", 1));
+        assert_eq!(source.presumed(Byte(18)), ("a.h", 10));
+        assert_eq!(source.presumed(Byte(25)), ("a.h", 11));
+        assert_eq!(
+            source.render(&Diagnostic::error(
+                Code::UnknownCharacter,
+                "fail to lex.",
+                Span::at(38)
+            )),
+            "a.h:3:3: error[E0001]: fail to lex.\n 3 |   $;\n   |   ^\n"
+        );
+        // a line in a comment is not a marker
+        let source = marked(
+            "main.c",
+            "/*\n# 100 \"fake.c\"\n*/\nint main() { return x; }",
+        );
+        assert_eq!(source.presumed(Byte(35)), ("main.c", 4));
+    }
+
+    // the source with the line markers the lexer skips
+    fn marked(name: &str, source: &str) -> SourceMap {
+        let mut markers = Vec::new();
+        let _ = TokenStream::tokenize_with_markers(source.to_string(), &mut markers);
+        SourceMap::new(name, source).with_markers(markers)
+    }
 }
@@ -1,124 +1,610 @@
-use crate::parser::{Node, Node::*};
+use crate::parser::{BinaryOp, Datum, Function, GlobalVariable, Node, NodeKind::*, Program};
+use crate::type_check::{operation_type, Type};
 
-pub fn generate_program03(nodes: &[Node]) -> String {
+// registers for the first six integer arguments in System V AMD64 ABI
+const ARGUMENT_REGISTERS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+const ARGUMENT_REGISTERS_32: [&str; 6] = ["edi", "esi", "edx", "ecx", "r8d", "r9d"];
+const ARGUMENT_REGISTERS_16: [&str; 6] = ["di", "si", "dx", "cx", "r8w", "r9w"];
+const ARGUMENT_REGISTERS_8: [&str; 6] = ["dil", "sil", "dl", "cl", "r8b", "r9b"];
+
+// the lower `size` bytes of an argument register
+fn argument_register(index: usize, size: usize) -> &'static str {
+    match size {
+        1 => ARGUMENT_REGISTERS_8[index],
+        2 => ARGUMENT_REGISTERS_16[index],
+        4 => ARGUMENT_REGISTERS_32[index],
+        _ => ARGUMENT_REGISTERS[index],
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Context {
+    label: usize,
+    // name of the function being generated
+    function: String,
+    // number of 8 byte values pushed since the prologue
+    depth: usize,
+    // offset from rbp of each local variable of the function
+    offsets: Vec<usize>,
+    // names of the file-scope variables
+    globals: Vec<String>,
+    // string literals in the order of their labels
+    strings: Vec<Vec<u8>>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // issue a label number unique in the assembly
+    fn new_label(&mut self) -> usize {
+        self.label += 1;
+        self.label
+    }
+}
+
+fn push(operand: &str, buffer: &mut String, context: &mut Context) {
+    buffer.push_str(&format!("   push {}\n", operand));
+    context.depth += 1;
+}
+
+fn pop(register: &str, buffer: &mut String, context: &mut Context) {
+    buffer.push_str(&format!("   pop {}\n", register));
+    context.depth -= 1;
+}
+
+// symbols are prefixed with an underscore on macOS
+pub fn symbol(name: &str) -> String {
+    if cfg!(target_os = "linux") {
+        name.to_string()
+    } else {
+        format!("_{}", name)
+    }
+}
+
+pub fn generate_program03(program: &Program) -> String {
     let mut buffer = String::new();
+    let mut context = Context::new();
 
     buffer.push_str(".intel_syntax noprefix\n");
+    context.globals = program
+        .globals
+        .iter()
+        .map(|global| global.name.clone())
+        .collect();
+    context.strings = program.strings.clone();
+    for function in &program.functions {
+        generate_function(function, &mut buffer, &mut context);
+    }
+    for global in &program.globals {
+        generate_global(global, &mut buffer);
+    }
+    generate_strings(&program.strings, &mut buffer);
+
+    // mark the stack as non-executable for the linker
     if cfg!(target_os = "linux") {
-        buffer.push_str(".global main\n\n");
-        buffer.push_str("main:\n");
+        buffer.push_str("\n.section .note.GNU-stack,\"\",@progbits\n");
+    }
+
+    buffer
+}
+
+// a variable without contents takes no space in the object file
+pub fn generate_global(global: &GlobalVariable, buffer: &mut String) {
+    let name = symbol(&global.name);
+    if global.data.is_empty() {
+        buffer.push_str("\n.bss\n");
     } else {
-        buffer.push_str(".global _main\n\n");
-        buffer.push_str("_main:\n");
+        buffer.push_str("\n.data\n");
+    }
+    buffer.push_str(&format!(".global {}\n", name));
+    buffer.push_str(&format!(".balign {}\n", global.ty.align()));
+    buffer.push_str(&format!("{}:\n", name));
+    if global.data.is_empty() {
+        buffer.push_str(&format!("   .zero {}\n", global.ty.size()));
+    }
+    for datum in &global.data {
+        match datum {
+            Datum::Integer(1, value) => buffer.push_str(&format!("   .byte {}\n", value)),
+            Datum::Integer(2, value) => buffer.push_str(&format!("   .short {}\n", value)),
+            Datum::Integer(4, value) => buffer.push_str(&format!("   .long {}\n", value)),
+            Datum::Integer(_, value) => buffer.push_str(&format!("   .quad {}\n", value)),
+            // resolved by the linker through a relocation
+            Datum::Address(label, offset) => {
+                buffer.push_str(&format!("   .quad {}{:+}\n", symbol(label), offset))
+            }
+            Datum::Literal(index, offset) => {
+                buffer.push_str(&format!("   .quad .LC{}{:+}\n", index, offset))
+            }
+            Datum::Zero(size) => buffer.push_str(&format!("   .zero {}\n", size)),
+        }
+    }
+}
+
+// read-only anonymous arrays, `.string` adds the terminating null
+pub fn generate_strings(strings: &[Vec<u8>], buffer: &mut String) {
+    if strings.is_empty() {
+        return;
+    }
+    if cfg!(target_os = "linux") {
+        buffer.push_str("\n.section .rodata\n");
+    } else {
+        buffer.push_str("\n.cstring\n");
     }
+    for (index, bytes) in strings.iter().enumerate() {
+        buffer.push_str(&format!(".LC{}:\n", index));
+        buffer.push_str(&format!("   .string \"{}\"\n", escape(bytes)));
+    }
+}
+
+// quote and backslash are escaped, bytes other than printable ascii are
+// written in octal
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(byte as char);
+            }
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    escaped
+}
+
+pub fn generate_function(function: &Function, buffer: &mut String, context: &mut Context) {
+    context.function = function.name.clone();
+    context.depth = 0;
+    context.offsets = function
+        .locals
+        .variables
+        .iter()
+        .map(|variable| variable.offset)
+        .collect();
+    buffer.push_str(&format!("\n.global {}\n", symbol(&function.name)));
+    buffer.push_str(&format!("{}:\n", symbol(&function.name)));
 
     // reserve stack for local value
     buffer.push_str("   push rbp\n");
     buffer.push_str("   mov rbp, rsp\n");
-    buffer.push_str("   sub rsp, 208\n");
+    buffer.push_str(&format!("   sub rsp, {}\n", function.locals.stack_size()));
 
-    for node in nodes {
-        generator(node, &mut buffer);
-        buffer.push_str("   pop rax\n");
+    // spill parameters to their slots with their own width
+    for (index, &variable) in function.params.iter().enumerate() {
+        let variable = &function.locals.variables[variable];
+        let size = variable.ty.size();
+        if index < ARGUMENT_REGISTERS.len() {
+            let register = argument_register(index, size);
+            buffer.push_str(&format!("   mov [rbp-{}], {}\n", variable.offset, register));
+        } else {
+            // the rest are passed on the stack above the return address,
+            // registers are spilled already so rdi is free
+            let argument = 16 + (index - ARGUMENT_REGISTERS.len()) * 8;
+            buffer.push_str(&format!("   mov rdi, [rbp+{}]\n", argument));
+            let register = argument_register(0, size);
+            buffer.push_str(&format!("   mov [rbp-{}], {}\n", variable.offset, register));
+        }
     }
 
+    for node in &function.body {
+        generate_statement(node, buffer, context);
+    }
+
+    // shared epilogue, `return` jumps here with its value in rax
+    buffer.push_str(&format!(".L.return.{}:\n", function.name));
     buffer.push_str("   mov rsp, rbp\n");
     buffer.push_str("   pop rbp\n");
     buffer.push_str("   ret\n");
+}
 
-    buffer
+pub fn generate_statement(node: &Node, buffer: &mut String, context: &mut Context) {
+    match &node.kind {
+        Return(value) => {
+            if let Some(value) = value {
+                generator(value, buffer, context);
+                pop("rax", buffer, context);
+            }
+            buffer.push_str(&format!("   jmp .L.return.{}\n", context.function));
+        }
+        Block(nodes) => {
+            for node in nodes {
+                generate_statement(node, buffer, context);
+            }
+        }
+        If(condition, then, otherwise) => {
+            let label = context.new_label();
+            generator(condition, buffer, context);
+            pop("rax", buffer, context);
+            buffer.push_str("   cmp rax, 0\n");
+            buffer.push_str(&format!("   je .L.else.{}\n", label));
+            generate_statement(then, buffer, context);
+            buffer.push_str(&format!("   jmp .L.end.{}\n", label));
+            buffer.push_str(&format!(".L.else.{}:\n", label));
+            if let Some(otherwise) = otherwise {
+                generate_statement(otherwise, buffer, context);
+            }
+            buffer.push_str(&format!(".L.end.{}:\n", label));
+        }
+        While(condition, body) => {
+            let label = context.new_label();
+            buffer.push_str(&format!(".L.begin.{}:\n", label));
+            generator(condition, buffer, context);
+            pop("rax", buffer, context);
+            buffer.push_str("   cmp rax, 0\n");
+            buffer.push_str(&format!("   je .L.end.{}\n", label));
+            generate_statement(body, buffer, context);
+            buffer.push_str(&format!("   jmp .L.begin.{}\n", label));
+            buffer.push_str(&format!(".L.end.{}:\n", label));
+        }
+        For(init, condition, step, body) => {
+            let label = context.new_label();
+            if let Some(init) = init {
+                generate_statement(init, buffer, context);
+            }
+            buffer.push_str(&format!(".L.begin.{}:\n", label));
+            if let Some(condition) = condition {
+                generator(condition, buffer, context);
+                pop("rax", buffer, context);
+                buffer.push_str("   cmp rax, 0\n");
+                buffer.push_str(&format!("   je .L.end.{}\n", label));
+            }
+            generate_statement(body, buffer, context);
+            if let Some(step) = step {
+                generate_statement(step, buffer, context);
+            }
+            buffer.push_str(&format!("   jmp .L.begin.{}\n", label));
+            buffer.push_str(&format!(".L.end.{}:\n", label));
+        }
+        _ => {
+            generator(node, buffer, context);
+            // keep the value of the last statement in rax
+            pop("rax", buffer, context);
+        }
+    }
 }
 
-pub fn generator(node: &Node, buffer: &mut String) {
-    match node {
+pub fn generator(node: &Node, buffer: &mut String, context: &mut Context) {
+    match &node.kind {
         Num(number) => {
-            buffer.push_str(&format!("   push {}\n", number));
+            // `push` takes at most a sign-extended 32-bit immediate
+            if *number >= i32::MIN as i64 && *number <= i32::MAX as i64 {
+                push(&number.to_string(), buffer, context);
+            } else {
+                buffer.push_str(&format!("   mov rax, {}\n", number));
+                push("rax", buffer, context);
+            }
         }
-        LVar(_) => {
-            generate_lvalue(node, buffer);
-            buffer.push_str("   pop rax\n");
-            buffer.push_str("   mov rax, [rax]\n");
-            buffer.push_str("   push rax\n");
+        // statements are handled by generate_statement
+        Return(_) | If(_, _, _) | While(_, _) | For(_, _, _, _) | Block(_) => unreachable!(),
+        // the operand is not evaluated, only its type matters
+        Sizeof(operand) => push(&operand.ty().size().to_string(), buffer, context),
+        Call { name, args } => {
+            // arguments beyond six are passed on the stack
+            let stack_args = args.len().saturating_sub(ARGUMENT_REGISTERS.len());
+            // rsp must be aligned to 16 bytes at `call`
+            let padding = (context.depth + stack_args) % 2;
+            if padding == 1 {
+                buffer.push_str("   sub rsp, 8\n");
+                context.depth += 1;
+            }
+            // evaluate from the last so that the seventh argument ends up on top
+            for arg in args.iter().rev() {
+                generator(arg, buffer, context);
+            }
+            for register in ARGUMENT_REGISTERS.iter().take(args.len()) {
+                pop(register, buffer, context);
+            }
+            // al holds the number of vector registers for variadic functions
+            buffer.push_str("   mov rax, 0\n");
+            buffer.push_str(&format!("   call {}\n", symbol(name)));
+            if stack_args + padding > 0 {
+                buffer.push_str(&format!("   add rsp, {}\n", (stack_args + padding) * 8));
+                context.depth -= stack_args + padding;
+            }
+            // the callee leaves the bits above its return type undefined
+            extend(node.ty(), buffer);
+            push("rax", buffer, context);
+        }
+        Cast(operand) => {
+            generator(operand, buffer, context);
+            pop("rax", buffer, context);
+            extend(node.ty(), buffer);
+            push("rax", buffer, context);
+        }
+        Addr(operand) => generate_lvalue(operand, buffer, context),
+        Deref(operand) => {
+            generator(operand, buffer, context);
+            pop("rax", buffer, context);
+            load(node.ty(), buffer);
+            push("rax", buffer, context);
+        }
+        Str(bytes) => {
+            let index = context
+                .strings
+                .iter()
+                .position(|string| string == bytes)
+                .expect("inner error: unknown string literal");
+            buffer.push_str(&format!("   lea rax, [rip+.LC{}]\n", index));
+            push("rax", buffer, context);
+        }
+        LVar(_) | GVar(_) => {
+            generate_lvalue(node, buffer, context);
+            pop("rax", buffer, context);
+            load(node.ty(), buffer);
+            push("rax", buffer, context);
         }
         Assign(left, right) => {
-            generate_lvalue(left, buffer);
-            generator(right, buffer);
-            buffer.push_str("   pop rdi\n");
-            buffer.push_str("   pop rax\n");
-            buffer.push_str("   mov [rax], rdi\n");
-            buffer.push_str("   push rdi\n");
+            generate_lvalue(left, buffer, context);
+            generator(right, buffer, context);
+            pop("rdi", buffer, context);
+            pop("rax", buffer, context);
+            store(left.ty(), buffer);
+            push("rdi", buffer, context);
+        }
+        AssignOp(operator, left, right) => {
+            generate_lvalue(left, buffer, context);
+            generator(right, buffer, context);
+            pop("rdi", buffer, context);
+            // the address stays on the stack while the value is computed
+            buffer.push_str("   mov rax, [rsp]\n");
+            load(left.ty(), buffer);
+            let ty = operation_type(*operator, left.ty(), right.ty());
+            extend(&ty, buffer);
+            operate(*operator, &ty, buffer);
+            extend(left.ty(), buffer);
+            buffer.push_str("   mov rdi, rax\n");
+            pop("rax", buffer, context);
+            store(left.ty(), buffer);
+            push("rdi", buffer, context);
+        }
+        PostInc(operand) | PostDec(operand) => {
+            generate_lvalue(operand, buffer, context);
+            pop("rax", buffer, context);
+            buffer.push_str("   mov rcx, rax\n");
+            load(node.ty(), buffer);
+            let sign = if let PostInc(_) = &node.kind {
+                "+"
+            } else {
+                "-"
+            };
+            // a pointer moves by the size of what it points to
+            let step = node.ty().base().map_or(1, Type::size);
+            buffer.push_str(&format!("   lea rdi, [rax{}{}]\n", sign, step));
+            // the old value is the result
+            push("rax", buffer, context);
+            buffer.push_str("   mov rax, rcx\n");
+            store(node.ty(), buffer);
+        }
+        Not(operand) => {
+            generator(operand, buffer, context);
+            pop("rax", buffer, context);
+            buffer.push_str("   cmp rax, 0\n");
+            buffer.push_str("   sete al\n");
+            zero_extend_al(buffer);
+            push("rax", buffer, context);
+        }
+        BitNot(operand) => {
+            generator(operand, buffer, context);
+            pop("rax", buffer, context);
+            buffer.push_str("   not rax\n");
+            extend(node.ty(), buffer);
+            push("rax", buffer, context);
+        }
+        LogAnd(left, right) | LogOr(left, right) => {
+            // `&&` stops at the first false, `||` at the first true
+            let label = context.new_label();
+            let (jump, short, full) = if let LogAnd(_, _) = &node.kind {
+                ("je", 0, 1)
+            } else {
+                ("jne", 1, 0)
+            };
+            for operand in [left, right].iter() {
+                generator(operand, buffer, context);
+                pop("rax", buffer, context);
+                buffer.push_str("   cmp rax, 0\n");
+                buffer.push_str(&format!("   {} .L.short.{}\n", jump, label));
+            }
+            buffer.push_str(&format!("   mov rax, {}\n", full));
+            buffer.push_str(&format!("   jmp .L.end.{}\n", label));
+            buffer.push_str(&format!(".L.short.{}:\n", label));
+            buffer.push_str(&format!("   mov rax, {}\n", short));
+            buffer.push_str(&format!(".L.end.{}:\n", label));
+            push("rax", buffer, context);
+        }
+        Cond(condition, then, otherwise) => {
+            let label = context.new_label();
+            generator(condition, buffer, context);
+            pop("rax", buffer, context);
+            buffer.push_str("   cmp rax, 0\n");
+            buffer.push_str(&format!("   je .L.else.{}\n", label));
+            // each branch leaves its value in rax so that one push follows
+            generator(then, buffer, context);
+            pop("rax", buffer, context);
+            buffer.push_str(&format!("   jmp .L.end.{}\n", label));
+            buffer.push_str(&format!(".L.else.{}:\n", label));
+            generator(otherwise, buffer, context);
+            pop("rax", buffer, context);
+            buffer.push_str(&format!(".L.end.{}:\n", label));
+            push("rax", buffer, context);
+        }
+        Comma(left, right) => {
+            generator(left, buffer, context);
+            pop("rax", buffer, context);
+            generator(right, buffer, context);
         }
         Add(left, right)
         | Sub(left, right)
         | Mul(left, right)
         | Div(left, right)
+        | Mod(left, right)
+        | Shl(left, right)
+        | Shr(left, right)
+        | BitAnd(left, right)
+        | BitOr(left, right)
+        | BitXor(left, right)
         | Eq(left, right)
         | Ne(left, right)
         | Le(left, right)
         | Lt(left, right) => {
             // first push left value
-            generator(left, buffer);
+            generator(left, buffer, context);
             // next push right value on the left value
-            generator(right, buffer);
+            generator(right, buffer, context);
 
             // right value -> rdi
-            buffer.push_str("   pop rdi\n");
+            pop("rdi", buffer, context);
             // left value -> rax
-            buffer.push_str("   pop rax\n");
+            pop("rax", buffer, context);
 
-            match node {
-                Add(_, _) => buffer.push_str("   add rax, rdi\n"),
-                Sub(_, _) => buffer.push_str("   sub rax, rdi\n"),
-                Mul(_, _) => buffer.push_str("   imul rax, rdi\n"),
-                Div(_, _) => {
-                    buffer.push_str("   cqo\n");
-                    buffer.push_str("   idiv rdi\n")
-                }
+            let ty = node.ty();
+            match &node.kind {
+                Add(_, _) => operate(BinaryOp::Add, ty, buffer),
+                Sub(_, _) => operate(BinaryOp::Sub, ty, buffer),
+                Mul(_, _) => operate(BinaryOp::Mul, ty, buffer),
+                Div(_, _) => operate(BinaryOp::Div, ty, buffer),
+                Mod(_, _) => operate(BinaryOp::Mod, ty, buffer),
+                Shl(_, _) => operate(BinaryOp::Shl, ty, buffer),
+                Shr(_, _) => operate(BinaryOp::Shr, ty, buffer),
+                BitAnd(_, _) => operate(BinaryOp::BitAnd, ty, buffer),
+                BitOr(_, _) => operate(BinaryOp::BitOr, ty, buffer),
+                BitXor(_, _) => operate(BinaryOp::BitXor, ty, buffer),
                 Eq(_, _) | Ne(_, _) | Le(_, _) | Lt(_, _) => {
                     buffer.push_str("   cmp rax, rdi\n");
-                    match node {
+                    // both operands have the type of the left one
+                    let unsigned = left.ty().is_unsigned();
+                    match &node.kind {
                         Eq(_, _) => {
                             buffer.push_str("   sete al\n");
                         }
                         Ne(_, _) => {
                             buffer.push_str("   setne al\n");
                         }
+                        Le(_, _) if unsigned => {
+                            buffer.push_str("   setbe al\n");
+                        }
                         Le(_, _) => {
                             buffer.push_str("   setle al\n");
                         }
+                        Lt(_, _) if unsigned => {
+                            buffer.push_str("   setb al\n");
+                        }
                         Lt(_, _) => {
                             buffer.push_str("   setl al\n");
                         }
                         _ => unreachable!(),
                     }
-                    if cfg!(target_os = "linux") {
-                        buffer.push_str("   movzb rax, al\n");
-                    } else {
-                        buffer.push_str("   movzx rax, al\n");
-                    }
+                    zero_extend_al(buffer);
                 }
 
                 Num(_) => unreachable!(),
                 _ => unreachable!(),
             }
 
-            buffer.push_str("   push rax\n")
+            push("rax", buffer, context);
+        }
+    }
+}
+
+// rax = rax op rdi in `ty`, the operands are extended to 64 bits so the
+// operation is done on the full registers and the result cut back to `ty`
+fn operate(operator: BinaryOp, ty: &Type, buffer: &mut String) {
+    match operator {
+        BinaryOp::Add => buffer.push_str("   add rax, rdi\n"),
+        BinaryOp::Sub => buffer.push_str("   sub rax, rdi\n"),
+        BinaryOp::Mul => buffer.push_str("   imul rax, rdi\n"),
+        BinaryOp::Div | BinaryOp::Mod => {
+            if ty.is_unsigned() {
+                buffer.push_str("   mov rdx, 0\n");
+                buffer.push_str("   div rdi\n");
+            } else {
+                buffer.push_str("   cqo\n");
+                buffer.push_str("   idiv rdi\n");
+            }
+            // the remainder is left in rdx
+            if operator == BinaryOp::Mod {
+                buffer.push_str("   mov rax, rdx\n");
+            }
+        }
+        BinaryOp::Shl | BinaryOp::Shr => {
+            buffer.push_str("   mov rcx, rdi\n");
+            let instruction = match (operator, ty.is_unsigned()) {
+                (BinaryOp::Shl, _) => "sal",
+                (_, true) => "shr",
+                (_, false) => "sar",
+            };
+            buffer.push_str(&format!("   {} rax, cl\n", instruction));
+        }
+        BinaryOp::BitAnd => buffer.push_str("   and rax, rdi\n"),
+        BinaryOp::BitOr => buffer.push_str("   or rax, rdi\n"),
+        BinaryOp::BitXor => buffer.push_str("   xor rax, rdi\n"),
+    }
+    extend(ty, buffer);
+}
+
+// sign or zero extend the lower bytes of rax holding a value of `ty`
+fn extend(ty: &Type, buffer: &mut String) {
+    match ty {
+        Type::Char => buffer.push_str("   movsx rax, al\n"),
+        Type::UnsignedChar => buffer.push_str("   movzx eax, al\n"),
+        Type::Short => buffer.push_str("   movsx rax, ax\n"),
+        Type::UnsignedShort => buffer.push_str("   movzx eax, ax\n"),
+        Type::Int => buffer.push_str("   movsxd rax, eax\n"),
+        // writing a 32-bit register clears the upper half
+        Type::UnsignedInt => buffer.push_str("   mov eax, eax\n"),
+        Type::Long | Type::UnsignedLong | Type::Pointer(_) | Type::Array(..) | Type::Void => {}
+    }
+}
+
+// rax = the value of `ty` at the address in rax
+fn load(ty: &Type, buffer: &mut String) {
+    match ty {
+        Type::Char => buffer.push_str("   movsx rax, byte ptr [rax]\n"),
+        Type::UnsignedChar => buffer.push_str("   movzx eax, byte ptr [rax]\n"),
+        Type::Short => buffer.push_str("   movsx rax, word ptr [rax]\n"),
+        Type::UnsignedShort => buffer.push_str("   movzx eax, word ptr [rax]\n"),
+        Type::Int => buffer.push_str("   movsxd rax, dword ptr [rax]\n"),
+        Type::UnsignedInt => buffer.push_str("   mov eax, dword ptr [rax]\n"),
+        Type::Long | Type::UnsignedLong | Type::Pointer(_) | Type::Void => {
+            buffer.push_str("   mov rax, [rax]\n")
         }
+        // an array is used as the address of its first element
+        Type::Array(..) => {}
+    }
+}
+
+// store the lower bytes of rdi for `ty` at the address in rax
+fn store(ty: &Type, buffer: &mut String) {
+    let register = argument_register(0, ty.size());
+    buffer.push_str(&format!("   mov [rax], {}\n", register));
+}
+
+fn zero_extend_al(buffer: &mut String) {
+    if cfg!(target_os = "linux") {
+        buffer.push_str("   movzb rax, al\n");
+    } else {
+        buffer.push_str("   movzx rax, al\n");
     }
 }
 
-pub fn generate_lvalue(node: &Node, buffer: &mut String) {
-    match node {
-        LVar(offset) => {
+pub fn generate_lvalue(node: &Node, buffer: &mut String, context: &mut Context) {
+    match &node.kind {
+        LVar(index) => {
             buffer.push_str("   mov rax, rbp\n");
-            buffer.push_str(&format!("   sub rax, {}\n", offset));
+            buffer.push_str(&format!("   sub rax, {}\n", context.offsets[*index]));
             // push lvalue's address to stack
-            buffer.push_str("   push rax\n");
+            push("rax", buffer, context);
         }
-        _ => {
-            eprintln!("Left value is needed to be variant.");
-            panic!();
+        GVar(index) => {
+            let name = symbol(&context.globals[*index]);
+            buffer.push_str(&format!("   lea rax, [rip+{}]\n", name));
+            push("rax", buffer, context);
         }
+        // the address is the value of the pointer
+        Deref(operand) => generator(operand, buffer, context),
+        _ => unreachable!("{:?} is not an lvalue", node.kind),
     }
 }
 
@@ -143,7 +629,7 @@ pub fn generate_program02(node: &Node) -> String {
 }
 
 pub fn generate_arithmetics_compare(node: &Node, buffer: &mut String) {
-    match node {
+    match &node.kind {
         Num(number) => {
             buffer.push_str(&format!("   push {}\n", number));
         }
@@ -165,7 +651,7 @@ pub fn generate_arithmetics_compare(node: &Node, buffer: &mut String) {
             // left value -> rax
             buffer.push_str("   pop rax\n");
 
-            match node {
+            match &node.kind {
                 Add(_, _) => buffer.push_str("   add rax, rdi\n"),
                 Sub(_, _) => buffer.push_str("   sub rax, rdi\n"),
                 Mul(_, _) => buffer.push_str("   imul rax, rdi\n"),
@@ -175,7 +661,7 @@ pub fn generate_arithmetics_compare(node: &Node, buffer: &mut String) {
                 }
                 Eq(_, _) | Ne(_, _) | Le(_, _) | Lt(_, _) => {
                     buffer.push_str("   cmp rax, rdi\n");
-                    match node {
+                    match &node.kind {
                         Eq(_, _) => {
                             buffer.push_str("   sete al\n");
                         }
@@ -228,7 +714,7 @@ pub fn generate_program01(node: &Node) -> String {
 }
 
 pub fn generate_arithmetics(node: &Node, buffer: &mut String) {
-    match node {
+    match &node.kind {
         Num(number) => {
             buffer.push_str(&format!("   push {}\n", number));
         }
@@ -243,7 +729,7 @@ pub fn generate_arithmetics(node: &Node, buffer: &mut String) {
             // left value -> rax
             buffer.push_str("   pop rax\n");
 
-            match node {
+            match &node.kind {
                 Add(_, _) => buffer.push_str("   add rax, rdi\n"),
                 Sub(_, _) => buffer.push_str("   sub rax, rdi\n"),
                 Mul(_, _) => buffer.push_str("   imul rax, rdi\n"),
@@ -266,7 +752,7 @@ mod tests_generator {
     use super::*;
     use crate::lexer::TokenStream;
     use crate::parser::*;
-    use crate::static_check::*;
+    use crate::type_check::check_types;
     use std::fs::File;
     use std::io::Write;
     use std::process::Command;
@@ -301,26 +787,29 @@ mod tests_generator {
             "a=1;",
             "a=1; b=a+2; c=a+b;",
             "a = 7; b = 7 - a; c = a > b; d = a * c; e = d + 10;",
+            "foo = 3; far = 4; foo * 10 + far;",
+            "x1 = 2; x_2 = x1 * 5; _x = x_2 - x1; _x;",
+            "a=1;b=2;c=3;d=4;e=5;f=6;g=7;h=8;i=9;j=10;k=11;l=12;m=13;n=14;o=15;p=16;q=17;r=18;s=19;t=20;u=21;v=22;w=23;x=24;y=25;z=26;zz=27;zz+a;",
+            "return 42;",
+            "a = 3; return a * 2; a = 5; 7;",
+            "returned = 5; return returned + 1;",
         ];
         let answers = vec![
             21, 17, 7, 0, 3, 25, 5, 0, 1, 1, 0, 1, 0, 1, 1, 0, 1, 1, 0, 0, 1, 1, 120, 0, 1, 4, 17,
+            34, 8, 28, 42, 6, 6,
         ];
-        for (case, answer) in cases
-            .into_iter()
-            .map(|s| s.to_string())
-            .zip(answers.into_iter())
-        {
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
             println!("{}", case);
             let mut stream = TokenStream::tokenize(case).unwrap();
-            verify_stream(&stream).unwrap();
-            let ast = parser(&mut stream).unwrap();
+            let mut ast = parser(&mut stream).unwrap();
+            check_types(&mut ast).unwrap();
             let program = generate_program03(&ast);
             let mut file = File::create("test06.s").unwrap();
             write!(file, "{}", program).unwrap();
             file.flush().unwrap();
             let out = Command::new("sh")
                 .arg("-c")
-                .arg(&format!("cc -o test06 test06.s; ./test06; echo $?",))
+                .arg("cc -o test06 test06.s; ./test06; echo $?")
                 .output()
                 .unwrap()
                 .stdout;
@@ -334,6 +823,550 @@ mod tests_generator {
         }
     }
 
+    #[test]
+    fn for_generate_control_flow() {
+        let cases = vec![
+            "if (1) return 3; return 5;",
+            "if (0) return 3; return 5;",
+            "a = 4; if (a == 4) b = 10; else b = 20; b;",
+            "a = 3; if (a == 4) b = 10; else b = 20; b;",
+            "a = 0; if (a) if (1) b = 1; else b = 2; else b = 3; b;",
+            "i = 0; while (i < 10) i = i + 1; return i;",
+            "i = 0; while (0) i = i + 1; i;",
+            "s = 0; for (i = 0; i <= 10; i = i + 1) s = s + i; return s;",
+            "i = 0; for (;;) if ((i = i + 1) == 7) return i; return 0;",
+            "s = 0; for (i = 0; i < 5; i = i + 1) for (j = 0; j < 3; j = j + 1) s = s + 1; s;",
+            "for (i = 0; i < 3; ) i = i + 1; i;",
+        ];
+        let answers = vec![3, 5, 10, 20, 3, 10, 0, 55, 7, 15, 3];
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
+            println!("{}", case);
+            let mut stream = TokenStream::tokenize(case).unwrap();
+            let mut ast = parser(&mut stream).unwrap();
+            check_types(&mut ast).unwrap();
+            let program = generate_program03(&ast);
+            let mut file = File::create("test07.s").unwrap();
+            write!(file, "{}", program).unwrap();
+            file.flush().unwrap();
+            let out = Command::new("sh")
+                .arg("-c")
+                .arg("cc -o test07 test07.s; ./test07; echo $?")
+                .output()
+                .unwrap()
+                .stdout;
+            let statement = std::str::from_utf8(&out).unwrap();
+            assert_eq!(statement.trim().parse::<i64>().unwrap(), answer);
+            Command::new("sh")
+                .arg("-c")
+                .arg("rm test07.s; rm test07")
+                .output()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn for_generate_block() {
+        let cases = vec![
+            "{ 1; 2; 3; }",
+            "int x = 1; { int x = 2; { int x = 3; } } x;",
+            "int x = 1; { int x = 2; y = x; } y * 10 + x;",
+            "int x = 1; { x = 5; } x;",
+            "int s = 0; for (int i = 0; i < 3; i = i + 1) { int j = i * 2; s = s + j; } s;",
+            "int a = 1; { int b = 2; { int c = 3; a = a + b + c; } } { int d = 4; a = a + d; } a;",
+            "int i = 0; while (i < 5) { int t = i; i = t + 1; } return i;",
+            "if (1) { int x = 7; return x; } return 0;",
+            "{} 9;",
+        ];
+        let answers = vec![3, 1, 21, 5, 6, 10, 5, 7, 9];
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
+            println!("{}", case);
+            let mut stream = TokenStream::tokenize(case).unwrap();
+            let mut ast = parser(&mut stream).unwrap();
+            check_types(&mut ast).unwrap();
+            let program = generate_program03(&ast);
+            let mut file = File::create("test08.s").unwrap();
+            write!(file, "{}", program).unwrap();
+            file.flush().unwrap();
+            let out = Command::new("sh")
+                .arg("-c")
+                .arg("cc -o test08 test08.s; ./test08; echo $?")
+                .output()
+                .unwrap()
+                .stdout;
+            let statement = std::str::from_utf8(&out).unwrap();
+            assert_eq!(statement.trim().parse::<i64>().unwrap(), answer);
+            Command::new("sh")
+                .arg("-c")
+                .arg("rm test08.s; rm test08")
+                .output()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn for_generate_function() {
+        let cases = vec![
+            "int main() { return 42; }",
+            "int two() { return 2; } int main() { return 40 + 2; }",
+            "int main() { int x = 3; if (x == 3) { return x * 4; } return 0; }",
+            "int main() { 5; }",
+            "int main(int argc) { return argc; }",
+            "int x; void set(int v) { if (v < 0) return; x = v; } int main(void) { set(7); set(-1); return x; }",
+        ];
+        let answers = vec![42, 42, 12, 5, 1, 7];
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
+            println!("{}", case);
+            let mut stream = TokenStream::tokenize(case).unwrap();
+            let mut ast = parser(&mut stream).unwrap();
+            check_types(&mut ast).unwrap();
+            let program = generate_program03(&ast);
+            let mut file = File::create("test09.s").unwrap();
+            write!(file, "{}", program).unwrap();
+            file.flush().unwrap();
+            let out = Command::new("sh")
+                .arg("-c")
+                .arg("cc -o test09 test09.s; ./test09; echo $?")
+                .output()
+                .unwrap()
+                .stdout;
+            let statement = std::str::from_utf8(&out).unwrap();
+            assert_eq!(statement.trim().parse::<i64>().unwrap(), answer);
+            Command::new("sh")
+                .arg("-c")
+                .arg("rm test09.s; rm test09")
+                .output()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn for_generate_parameters() {
+        // call the compiled function from C to pass the arguments
+        let cases = vec![
+            (
+                "int f(int a, int b) { return a - b; }",
+                "int f(int, int); int main() { return f(10, 3); }",
+                7,
+            ),
+            (
+                "int f(int a, int b, int c, int d, int e, int g) { return a + b + c + d + e + g; }",
+                "int f(int, int, int, int, int, int); int main() { return f(1, 2, 3, 4, 5, 6); }",
+                21,
+            ),
+            (
+                "int f(int a, int b, int c, int d, int e, int g, int h, int i) { return h * 10 + i - a; }",
+                "int f(int, int, int, int, int, int, int, int);\
+                 int main() { return f(1, 2, 3, 4, 5, 6, 7, 8); }",
+                77,
+            ),
+        ];
+        for (case, caller, answer) in cases {
+            println!("{}", case);
+            let mut stream = TokenStream::tokenize(case.to_string()).unwrap();
+            let mut ast = parser(&mut stream).unwrap();
+            check_types(&mut ast).unwrap();
+            let program = generate_program03(&ast);
+            let mut file = File::create("test10.s").unwrap();
+            write!(file, "{}", program).unwrap();
+            file.flush().unwrap();
+            let mut file = File::create("test10_main.c").unwrap();
+            write!(file, "{}", caller).unwrap();
+            file.flush().unwrap();
+            let out = Command::new("sh")
+                .arg("-c")
+                .arg("cc -o test10 test10.s test10_main.c; ./test10; echo $?")
+                .output()
+                .unwrap()
+                .stdout;
+            let statement = std::str::from_utf8(&out).unwrap();
+            assert_eq!(statement.trim().parse::<i64>().unwrap(), answer);
+            Command::new("sh")
+                .arg("-c")
+                .arg("rm test10.s; rm test10_main.c; rm test10")
+                .output()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn for_generate_call() {
+        let cases = vec![
+            "int main() { return ret3(); }",
+            "int main() { return sub2(10, 4); }",
+            "int main() { return add8(1, 2, 3, 4, 5, 6, 7, 8); }",
+            "int main() { int x = 1; return add8(x, 2, 3, 4, 5, 6, 7, sub2(20, 12)) + x; }",
+            "int main() { return 1 + (2 + (3 + aligned())); }",
+            "int main() { return 1 + (2 + (3 + add8(1, 2, 3, 4, 5, 6, 7, aligned()))); }",
+            "int main() { exit(9); return 0; }",
+            "int plus(int x, int y) { return x + y; } int main() { return plus(3, 4); }",
+            "int fib(int n) { if (n <= 1) return n; return fib(n - 1) + fib(n - 2); }\
+             int main() { return fib(10); }",
+            "int seven(int a, int b, int c, int d, int e, int f, int g) { return g - a; }\
+             int main() { return seven(1, 2, 3, 4, 5, 6, 7); }",
+        ];
+        let answers = vec![3, 6, 36, 37, 7, 35, 9, 7, 55, 6];
+        let mut file = File::create("test11_lib.c").unwrap();
+        write!(
+            file,
+            "#include <stdint.h>\n\
+             long ret3() {{ return 3; }}\n\
+             long sub2(long a, long b) {{ return a - b; }}\n\
+             long add8(long a, long b, long c, long d, long e, long f, long g, long h) {{\n\
+                 return a + b + c + d + e + f + g + h;\n\
+             }}\n\
+             long aligned() {{\n\
+                 if ((uintptr_t)__builtin_frame_address(0) % 16 != 0) return 100;\n\
+                 return 1;\n\
+             }}\n"
+        )
+        .unwrap();
+        file.flush().unwrap();
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
+            println!("{}", case);
+            let mut stream = TokenStream::tokenize(case).unwrap();
+            let mut ast = parser(&mut stream).unwrap();
+            check_types(&mut ast).unwrap();
+            let program = generate_program03(&ast);
+            let mut file = File::create("test11.s").unwrap();
+            write!(file, "{}", program).unwrap();
+            file.flush().unwrap();
+            let out = Command::new("sh")
+                .arg("-c")
+                .arg("cc -o test11 test11.s test11_lib.c; ./test11; echo $?")
+                .output()
+                .unwrap()
+                .stdout;
+            let statement = std::str::from_utf8(&out).unwrap();
+            assert_eq!(statement.trim().parse::<i64>().unwrap(), answer);
+            Command::new("sh")
+                .arg("-c")
+                .arg("rm test11.s; rm test11")
+                .output()
+                .unwrap();
+        }
+        Command::new("sh")
+            .arg("-c")
+            .arg("rm test11_lib.c")
+            .output()
+            .unwrap();
+    }
+
+    #[test]
+    fn for_generate_literal() {
+        let cases = vec![
+            "return 'a';",
+            "return '\\n' + '\\x41' + '\\101'; // 10 + 65 + 65",
+            "/* comment */ int main() { /* return 1; */ return '\\0' - '\\377'; }",
+            "# 1 \"test.c\"\nint x = 'z' - 'a';\n# 10\nreturn x; // done",
+            "return 0x1F + 017 + 0b101 + 10u + 2L;",
+            "return 4294967296 / 65536 / 65536;",
+            "return 0x7fffffffffffffff - 0x7ffffffffffffff0;",
+        ];
+        let answers = vec![97, 140, 1, 25, 63, 1, 15];
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
+            println!("{}", case);
+            let mut stream = TokenStream::tokenize(case).unwrap();
+            let mut ast = parser(&mut stream).unwrap();
+            check_types(&mut ast).unwrap();
+            let program = generate_program03(&ast);
+            let mut file = File::create("test12.s").unwrap();
+            write!(file, "{}", program).unwrap();
+            file.flush().unwrap();
+            let out = Command::new("sh")
+                .arg("-c")
+                .arg("cc -o test12 test12.s; ./test12; echo $?")
+                .output()
+                .unwrap()
+                .stdout;
+            let statement = std::str::from_utf8(&out).unwrap();
+            assert_eq!(statement.trim().parse::<i64>().unwrap(), answer);
+            Command::new("sh")
+                .arg("-c")
+                .arg("rm test12.s; rm test12")
+                .output()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn for_generate_operators() {
+        let cases = vec![
+            "return 17 % 5;",
+            "return 6 & 3;",
+            "return 6 | 3;",
+            "return 6 ^ 3;",
+            "return ~5 + 10;",
+            "return !0 + !7;",
+            "return 1 << 4;",
+            "return 256 >> 3;",
+            "return -16 >> 2 == -4;",
+            "return 3 && 0;",
+            "return 3 && 4;",
+            "return 0 || 0;",
+            "return 0 || 5;",
+            "int x = 0; 0 && (x = 1); 1 || (x = 2); return x;",
+            "int x = 0; 1 && (x = 3); 0 || (x = x + 4); return x;",
+            "return 1 ? 7 : 9;",
+            "return 0 ? 7 : 0 ? 8 : 9;",
+            "int x = 1, y = 2; x = (y = 5, y + 1); return x;",
+            "int a = 10; a += 5; a -= 3; a *= 4; a /= 6; a %= 5; return a;",
+            "int a = 1; a <<= 5; a >>= 2; a |= 3; a &= 10; a ^= 15; return a;",
+            "int a = 5; int b = a++; return a * 10 + b;",
+            "int a = 5; int b = a--; return a * 10 + b;",
+            "int a = 5; int b = ++a; return a * 10 + b;",
+            "int a = 5; int b = --a; return a * 10 + b;",
+            "int s = 0; for (int i = 0, j = 10; i < j; i++, j--) s += j - i; return s;",
+            "int x = 3, y; y = x++; y += x; return y;",
+            "return - -3;",
+            "return -+-3;",
+            "int x = 4; return -(-x) * - - 2;",
+            "return !!5 + ~-1;",
+            "int a = 3; return - -a * -a + 20;",
+            "int a = 1; ++a; return - --a;",
+        ];
+        let answers = vec![
+            2, 2, 7, 5, 4, 1, 16, 32, 1, 0, 1, 0, 1, 0, 7, 7, 9, 6, 3, 5, 65, 45, 66, 44, 30, 7, 3,
+            3, 8, 1, 11, 255,
+        ];
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
+            println!("{}", case);
+            let mut stream = TokenStream::tokenize(case).unwrap();
+            let mut ast = parser(&mut stream).unwrap();
+            check_types(&mut ast).unwrap();
+            let program = generate_program03(&ast);
+            let mut file = File::create("test13.s").unwrap();
+            write!(file, "{}", program).unwrap();
+            file.flush().unwrap();
+            let out = Command::new("sh")
+                .arg("-c")
+                .arg("cc -o test13 test13.s; ./test13; echo $?")
+                .output()
+                .unwrap()
+                .stdout;
+            let statement = std::str::from_utf8(&out).unwrap();
+            assert_eq!(statement.trim().parse::<i64>().unwrap(), answer);
+            Command::new("sh")
+                .arg("-c")
+                .arg("rm test13.s; rm test13")
+                .output()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn for_generate_types() {
+        let cases = vec![
+            "char c = 300; return c;",
+            "char c = 127; c = c + 1; return c == -128;",
+            "unsigned char u = 255; u++; return u;",
+            "char c = -1; unsigned char u = c; return u;",
+            "short s = -1; unsigned short u = s; return u == 65535;",
+            "int i = -1; unsigned u = i; return u > 0;",
+            "int i = -1; return i < 0u;",
+            "long l = -1; return l < 0u;",
+            "unsigned u = 0; u = u - 1; return u / 2 == 2147483647;",
+            "int i = -8; return i >> 1 == -4;",
+            "unsigned u = -8; return u >> 29;",
+            "long l = 1; l = l << 40; return l >> 38;",
+            "unsigned long x = -1; return x >> 60;",
+            "char c = 10; c *= 30; return c;",
+            "short s = 32767; s++; return s == -32768;",
+            "long unsigned int x = 3; int long y = 4; return x * y;",
+            "unsigned u = 7; return -u % 10;",
+            "char f(char c) { return c + 1; } int main() { return f(127) == -128; }",
+            "long f(long a, char b, short c, int d, unsigned char e, long g, char h) { return a + b + c + d + e + g + h; } int main() { return f(1, 2, 3, 4, 5, 6, -7); }",
+            "void f() { 1; } int main() { f(); return 3; }",
+        ];
+        let answers = vec![
+            44, 1, 0, 255, 1, 1, 0, 1, 1, 1, 7, 4, 15, 44, 1, 12, 9, 1, 14, 3,
+        ];
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
+            println!("{}", case);
+            let mut stream = TokenStream::tokenize(case).unwrap();
+            let mut ast = parser(&mut stream).unwrap();
+            check_types(&mut ast).unwrap();
+            let program = generate_program03(&ast);
+            let mut file = File::create("test14.s").unwrap();
+            write!(file, "{}", program).unwrap();
+            file.flush().unwrap();
+            let out = Command::new("sh")
+                .arg("-c")
+                .arg("cc -o test14 test14.s; ./test14; echo $?")
+                .output()
+                .unwrap()
+                .stdout;
+            let statement = std::str::from_utf8(&out).unwrap();
+            assert_eq!(statement.trim().parse::<i64>().unwrap(), answer);
+            Command::new("sh")
+                .arg("-c")
+                .arg("rm test14.s; rm test14")
+                .output()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn for_generate_pointers() {
+        let cases = vec![
+            "int x = 3; int *p = &x; *p = 5; return x;",
+            "int x = 3; int *p = &x; int **pp = &p; **pp = 7; return x;",
+            "int x = 9; return *&x;",
+            "char c = 'a'; char *p = &c; *p += 2; return c;",
+            "int x = 2; int *p = &x; (*p)++; ++*p; return x;",
+            "long x = 5; long *p = &x; p++; p--; return *p;",
+            "int x; int *p = &x; return p + 3 - p;",
+            "long a; long b; return &a - &b;",
+            "long a = 1; long b = 2; long *p = &b; return *(p + 1);",
+            "int x = 1; int *p = &x; return (p == &x) + (p + 1 > p);",
+            "int *p = 0; return !p;",
+            "void swap(int *a, int *b) { int t = *a; *a = *b; *b = t; } int main() { int x = 1; int y = 2; swap(&x, &y); return x * 10 + y; }",
+            "long sum(long *p, long n) { long s = 0; while (n--) s += *p--; return s; } int main() { long a = 1; long b = 2; long c = 3; return sum(&a, 3); }",
+            "int *id(int *p) { return p; } int main() { int x = 4; *id(&x) *= 3; return x; }",
+        ];
+        let answers = vec![5, 7, 9, 99, 4, 5, 3, 1, 1, 2, 1, 21, 6, 12];
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
+            println!("{}", case);
+            let mut stream = TokenStream::tokenize(case).unwrap();
+            let mut ast = parser(&mut stream).unwrap();
+            check_types(&mut ast).unwrap();
+            let program = generate_program03(&ast);
+            let mut file = File::create("test15.s").unwrap();
+            write!(file, "{}", program).unwrap();
+            file.flush().unwrap();
+            let out = Command::new("sh")
+                .arg("-c")
+                .arg("cc -o test15 test15.s; ./test15; echo $?")
+                .output()
+                .unwrap()
+                .stdout;
+            let statement = std::str::from_utf8(&out).unwrap();
+            assert_eq!(statement.trim().parse::<i64>().unwrap(), answer);
+            Command::new("sh")
+                .arg("-c")
+                .arg("rm test15.s; rm test15")
+                .output()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn for_generate_arrays() {
+        let cases = vec![
+            "int a[3]; a[0] = 1; a[1] = 2; a[2] = 3; return a[0] + a[1] + a[2];",
+            "int a[3]; *a = 4; *(a + 1) = 5; return a[0] * a[1];",
+            "int a[2][3]; int *p = a[1]; p[2] = 7; return a[1][2];",
+            "int a[2][3]; for (int i = 0; i < 2; i++) for (int j = 0; j < 3; j++) a[i][j] = i * 3 + j; return a[1][1] * 10 + a[0][2];",
+            "char s[4]; s[0] = 1; s[1] = 2; s[2] = 3; s[3] = 4; return s[1] + s[3];",
+            "char c; int a[10]; char d; c = 1; d = 2; a[9] = 3; return c + d + a[9];",
+            "long a[4]; return &a[3] - &a[0];",
+            "int a[2][3]; return &a[1][0] - &a[0][0];",
+            "int a[3]; int *p = a; p++; *p = 9; return 1[a];",
+            "int a[2][3]; return sizeof a + sizeof a[0] + sizeof(char) + sizeof(int *[3]);",
+            "char x; return sizeof(x + 1) + sizeof x;",
+            "int sum(int *p, int n) { int s = 0; for (int i = 0; i < n; i++) s += p[i]; return s; } int main() { int a[4]; for (int i = 0; i < 4; i++) a[i] = i + 1; return sum(a, 4); }",
+            "int first(int a[2]) { return a[0]; } int main() { int a[2]; a[0] = 42; return first(a); }",
+        ];
+        let answers = vec![6, 20, 7, 42, 6, 6, 3, 3, 9, 61, 5, 10, 42];
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
+            println!("{}", case);
+            let mut stream = TokenStream::tokenize(case).unwrap();
+            let mut ast = parser(&mut stream).unwrap();
+            check_types(&mut ast).unwrap();
+            let program = generate_program03(&ast);
+            let mut file = File::create("test16.s").unwrap();
+            write!(file, "{}", program).unwrap();
+            file.flush().unwrap();
+            let out = Command::new("sh")
+                .arg("-c")
+                .arg("cc -o test16 test16.s; ./test16; echo $?")
+                .output()
+                .unwrap()
+                .stdout;
+            let statement = std::str::from_utf8(&out).unwrap();
+            assert_eq!(statement.trim().parse::<i64>().unwrap(), answer);
+            Command::new("sh")
+                .arg("-c")
+                .arg("rm test16.s; rm test16")
+                .output()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn for_generate_globals() {
+        let cases = vec![
+            "int x; int main() { x = 3; return x; }",
+            "int x = 5; int inc() { x++; return 0; } int main() { inc(); inc(); return x; }",
+            "char c = -1; short s = 300; long l = 1 << 20; int main() { return c + s + (l >> 16); }",
+            "int a[4] = {1, 2, 3}; int main() { return a[0] + a[1] + a[2] + a[3]; }",
+            "int m[2][2] = {{1, 2}, {3}}; int main() { return m[0][1] * 10 + m[1][0] + m[1][1]; }",
+            "int a[3] = {4, 5, 6}; int *p = &a[1]; int *q = a + 2; int main() { return *p * 10 + *q; }",
+            "int x = 7; int *p = &x; int main() { *p = 9; return x; }",
+            "long n = sizeof(int) * 3 - 2 ? 10 : 20; int main() { return n; }",
+            "int x = 1; int main() { int x = 2; { int y = x; } return x; }",
+            "char b; int x; int main() { b = 1; x = 2; return b + x; }",
+            "void *p = &p; int g = sizeof g; int main() { return (p == &p) + g; }",
+        ];
+        let answers = vec![3, 7, 59, 6, 23, 56, 9, 10, 2, 3, 5];
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
+            println!("{}", case);
+            let mut stream = TokenStream::tokenize(case).unwrap();
+            let mut ast = parser(&mut stream).unwrap();
+            check_types(&mut ast).unwrap();
+            let program = generate_program03(&ast);
+            let mut file = File::create("test17.s").unwrap();
+            write!(file, "{}", program).unwrap();
+            file.flush().unwrap();
+            let out = Command::new("sh")
+                .arg("-c")
+                .arg("cc -o test17 test17.s; ./test17; echo $?")
+                .output()
+                .unwrap()
+                .stdout;
+            let statement = std::str::from_utf8(&out).unwrap();
+            assert_eq!(statement.trim().parse::<i64>().unwrap(), answer);
+            Command::new("sh")
+                .arg("-c")
+                .arg("rm test17.s; rm test17")
+                .output()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn for_generate_strings() {
+        let cases = vec![
+            "int main() { printf(\"%d\\n\", 42); return 0; }",
+            "int main() { char *s = \"abc\"; printf(\"%s %c %d\\n\", s, s[1], sizeof \"abc\"); return 0; }",
+            "char *g = \"tab\\there\" + 4; int main() { printf(\"[%s]\\n\", g); return 0; }",
+            "int main() { printf(\"\\\"%s\\\"\\n\", \"q\"); return \"xyz\"[2] - 'x'; }",
+        ];
+        let answers = vec!["42\n0", "abc b 4\n0", "[here]\n0", "\"q\"\n2"];
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
+            println!("{}", case);
+            let mut stream = TokenStream::tokenize(case).unwrap();
+            let mut ast = parser(&mut stream).unwrap();
+            check_types(&mut ast).unwrap();
+            let program = generate_program03(&ast);
+            let mut file = File::create("test18.s").unwrap();
+            write!(file, "{}", program).unwrap();
+            file.flush().unwrap();
+            let out = Command::new("sh")
+                .arg("-c")
+                .arg("cc -o test18 test18.s; ./test18; echo $?")
+                .output()
+                .unwrap()
+                .stdout;
+            let statement = std::str::from_utf8(&out).unwrap();
+            assert_eq!(statement.trim(), answer);
+            Command::new("sh")
+                .arg("-c")
+                .arg("rm test18.s; rm test18")
+                .output()
+                .unwrap();
+        }
+    }
+
     #[test]
     fn for_generate_program02() {
         let cases = vec![
@@ -365,20 +1398,16 @@ mod tests_generator {
         let answers = vec![
             21, 17, 7, 0, 3, 25, 5, 0, 1, 1, 0, 1, 0, 1, 1, 0, 1, 1, 0, 0, 1, 1, 120, 0,
         ];
-        for (case, answer) in cases
-            .into_iter()
-            .map(|s| s.to_string())
-            .zip(answers.into_iter())
-        {
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
             let mut stream = TokenStream::tokenize(case).unwrap();
-            let ast = expr(&mut stream);
+            let ast = expr(&mut stream, &mut Locals::new()).unwrap();
             let program = generate_program02(&ast);
             let mut file = File::create("test05.s").unwrap();
             write!(file, "{}", program).unwrap();
             file.flush().unwrap();
             let out = Command::new("sh")
                 .arg("-c")
-                .arg(&format!("cc -o test05 test05.s; ./test05; echo $?",))
+                .arg("cc -o test05 test05.s; ./test05; echo $?")
                 .output()
                 .unwrap()
                 .stdout;
@@ -404,20 +1433,16 @@ mod tests_generator {
             "-3*+5+20",
         ];
         let answers = vec![21, 17, 7, 0, 3, 25, 5];
-        for (case, answer) in cases
-            .into_iter()
-            .map(|s| s.to_string())
-            .zip(answers.into_iter())
-        {
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
             let mut stream = TokenStream::tokenize(case).unwrap();
-            let ast = expr(&mut stream);
+            let ast = expr(&mut stream, &mut Locals::new()).unwrap();
             let program = generate_program01(&ast);
             let mut file = File::create("test04.s").unwrap();
             write!(file, "{}", program).unwrap();
             file.flush().unwrap();
             let out = Command::new("sh")
                 .arg("-c")
-                .arg(&format!("cc -o test04 test04.s; ./test04; echo $?",))
+                .arg("cc -o test04 test04.s; ./test04; echo $?")
                 .output()
                 .unwrap()
                 .stdout;
@@ -514,7 +1539,7 @@ mod tests_generator {
             .zip(answers.into_iter().map(|s| s.to_string()))
         {
             let mut stream = TokenStream::tokenize(case).unwrap();
-            let ast = expr(&mut stream);
+            let ast = expr(&mut stream, &mut Locals::new()).unwrap();
             let mut buffer = String::new();
             generate_arithmetics(&ast, &mut buffer);
             assert_eq!(buffer, answer);
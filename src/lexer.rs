@@ -1,248 +1,619 @@
 use crate::error::*;
-use std::iter::IntoIterator;
 use Token::*;
 use Word::*;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct TokenStream {
-    pub sequence: std::collections::LinkedList<Token>,
-    pub position: std::collections::LinkedList<usize>,
+    // kept so that the text of every token is at hand
+    pub source: String,
+    pub tokens: Vec<Lexeme>,
+    // index of the next token for the parser
+    pub cursor: usize,
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Lexeme {
+    pub token: Token,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Token {
     Reserved(Word),
-    Ident(u64),
-    Number(i64),
+    Ident(String),
+    // unsigned values are kept as their bits
+    Number(i64, IntegerType),
+    // value of the char as char is signed
+    CharLiteral(i64),
+    // bytes without the terminating null
+    StringLiteral(Vec<u8>),
     SemiColon,
     Eof,
 }
 
+impl Token {
+    // words which make up a type such as `unsigned long`
+    pub fn is_type(&self) -> bool {
+        matches!(
+            self,
+            Reserved(Int | Char | Short | Long | Void | Signed | Unsigned)
+        )
+    }
+}
+
+// type of an integer literal, int is 32 bits and long is 64 bits
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IntegerType {
+    Int,
+    UnsignedInt,
+    Long,
+    UnsignedLong,
+    LongLong,
+    UnsignedLongLong,
+}
+
+impl IntegerType {
+    fn is_unsigned(self) -> bool {
+        matches!(
+            self,
+            IntegerType::UnsignedInt | IntegerType::UnsignedLong | IntegerType::UnsignedLongLong
+        )
+    }
+
+    // 0 for int, 1 for long and 2 for long long
+    fn rank(self) -> usize {
+        match self {
+            IntegerType::Int | IntegerType::UnsignedInt => 0,
+            IntegerType::Long | IntegerType::UnsignedLong => 1,
+            IntegerType::LongLong | IntegerType::UnsignedLongLong => 2,
+        }
+    }
+
+    fn max(self) -> u64 {
+        match (self.rank(), self.is_unsigned()) {
+            (0, false) => i32::MAX as u64,
+            (0, true) => u32::MAX as u64,
+            (_, false) => i64::MAX as u64,
+            (_, true) => u64::MAX,
+        }
+    }
+}
+
+const INTEGER_TYPES: [IntegerType; 6] = [
+    IntegerType::Int,
+    IntegerType::UnsignedInt,
+    IntegerType::Long,
+    IntegerType::UnsignedLong,
+    IntegerType::LongLong,
+    IntegerType::UnsignedLongLong,
+];
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Word {
     LeftBra,
     RightBra,
+    LeftBrace,
+    RightBrace,
+    LeftSquare,
+    RightSquare,
+    Comma,
     Add,
     Sub,
     Mul,
     Div,
+    Mod,
     Eq,
     Ne,
     Le,
     Lt,
     Ge,
     Gt,
+    Shl,
+    Shr,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    Not,
+    LogAnd,
+    LogOr,
+    Question,
+    Colon,
+    Inc,
+    Dec,
     Assign,
+    AddAssign,
+    SubAssign,
+    MulAssign,
+    DivAssign,
+    ModAssign,
+    ShlAssign,
+    ShrAssign,
+    AndAssign,
+    OrAssign,
+    XorAssign,
+    Return,
+    If,
+    Else,
+    While,
+    For,
+    Int,
+    Char,
+    Short,
+    Long,
+    Void,
+    Signed,
+    Unsigned,
+    Sizeof,
 }
 
+const KEYWORDS: [(&str, Word); 13] = [
+    ("return", Return),
+    ("if", If),
+    ("else", Else),
+    ("while", While),
+    ("for", For),
+    ("int", Int),
+    ("char", Char),
+    ("short", Short),
+    ("long", Long),
+    ("void", Void),
+    ("signed", Signed),
+    ("unsigned", Unsigned),
+    ("sizeof", Sizeof),
+];
+
+// longer operators first so that `<=` is not lexed as `<` and `=`
+const ORDERS: [(&str, Word); 12] = [
+    ("<<=", ShlAssign),
+    (">>=", ShrAssign),
+    ("<<", Shl),
+    (">>", Shr),
+    ("<=", Le),
+    (">=", Ge),
+    ("==", Eq),
+    ("!=", Ne),
+    ("<", Lt),
+    (">", Gt),
+    ("=", Assign),
+    ("!", Not),
+];
+
+const OPERATORS: [(&str, Word); 24] = [
+    ("++", Inc),
+    ("--", Dec),
+    ("+=", AddAssign),
+    ("-=", SubAssign),
+    ("*=", MulAssign),
+    ("/=", DivAssign),
+    ("%=", ModAssign),
+    ("&=", AndAssign),
+    ("|=", OrAssign),
+    ("^=", XorAssign),
+    ("&&", LogAnd),
+    ("||", LogOr),
+    ("+", Add),
+    ("-", Sub),
+    ("*", Mul),
+    ("/", Div),
+    ("%", Mod),
+    ("&", BitAnd),
+    ("|", BitOr),
+    ("^", BitXor),
+    ("~", BitNot),
+    ("?", Question),
+    (":", Colon),
+    (",", Comma),
+];
+
+const BRACKETS: [(&str, Word); 6] = [
+    ("(", LeftBra),
+    (")", RightBra),
+    ("{", LeftBrace),
+    ("}", RightBrace),
+    ("[", LeftSquare),
+    ("]", RightSquare),
+];
+
+// what went wrong and the byte offset of the problem in the rest of the input
+type LexError = (Code, &'static str, usize);
+
 impl<'a> IntoIterator for &'a TokenStream {
     type Item = &'a Token;
-    type IntoIter = std::collections::linked_list::Iter<'a, Token>;
+    type IntoIter = std::iter::Map<std::slice::Iter<'a, Lexeme>, fn(&Lexeme) -> &Token>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.sequence.iter()
+        self.tokens.iter().map(|lexeme| &lexeme.token)
     }
 }
 
 impl TokenStream {
-    pub fn tokenize(mut program: String) -> Result<Self, (String, Byte)> {
-        let mut sequence = std::collections::LinkedList::<Token>::new();
-        let mut position = std::collections::LinkedList::<usize>::new();
+    pub fn tokenize(program: String) -> Result<Self, Diagnostic> {
+        TokenStream::tokenize_with_markers(program, &mut Vec::new())
+    }
+
+    // line markers are pushed to `markers` as they are skipped, so those
+    // before a failure are kept as well
+    pub fn tokenize_with_markers(
+        program: String,
+        markers: &mut Vec<Marker>,
+    ) -> Result<Self, Diagnostic> {
+        let mut tokens = Vec::new();
         let mut start_at = 0usize;
-        while !program.is_empty() {
-            // lex semicolon
-            let (string, ret, width) = TokenStream::consume_semicolon(program);
-            program = string;
-            if let Some(token) = ret {
-                sequence.push_back(token);
-                position.push_back(start_at);
-                start_at += width;
-                continue;
-            }
+        // only whitespaces since the last newline
+        let mut line_start = true;
+        while start_at < program.len() {
+            // everything is lexed from a borrowed cursor, nothing is copied
+            let rest = &program[start_at..];
+            let fail = |(code, message, offset): LexError| {
+                Diagnostic::error(code, message, Span::at(start_at + offset))
+            };
 
-            // lex brackets
-            let (string, ret, width) = TokenStream::consume_bracket(program);
-            program = string;
-            if let Some(token) = ret {
-                sequence.push_back(token);
-                position.push_back(start_at);
-                start_at += width;
-                continue;
+            // skip line markers written by the preprocessor
+            if line_start {
+                if let Some((width, line, file)) = TokenStream::consume_line_marker(rest) {
+                    start_at += width;
+                    markers.push((Byte(start_at), line, file));
+                    continue;
+                }
             }
 
-            // lex as number
-            let (string, ret, width) = TokenStream::consume_number(program);
-            program = string;
-            if let Some(token) = ret {
-                sequence.push_back(token);
-                position.push_back(start_at);
+            // skip comments
+            let width = TokenStream::consume_comment(rest).map_err(fail)?;
+            if width > 0 {
+                line_start = false;
                 start_at += width;
                 continue;
             }
 
-            // lex as orderd operator
-            let (string, ret, width) = TokenStream::consume_order(program);
-            program = string;
-            if let Some(token) = ret {
-                sequence.push_back(token);
-                position.push_back(start_at);
+            // skip whitespaces
+            let width = TokenStream::consume_whitespace(rest);
+            if width > 0 {
+                line_start = line_start || rest[..width].contains('\n');
                 start_at += width;
                 continue;
             }
+            line_start = false;
 
-            // lex as alphabet
-            let (string, ret, width) = TokenStream::consume_alphabetic(program);
-            program = string;
-            if let Some(token) = ret {
-                sequence.push_back(token);
-                position.push_back(start_at);
-                start_at += width;
-                continue;
+            // literals, semicolon, brackets, numbers, ordered operators,
+            // identifiers and the other operators in this order
+            let mut lexed = TokenStream::consume_literal(rest).map_err(fail)?;
+            if lexed.is_none() {
+                lexed = TokenStream::consume_semicolon(rest)
+                    .or_else(|| TokenStream::consume_bracket(rest));
             }
-
-            // lex as operator
-            let (string, ret, width) = TokenStream::consume_operator(program);
-            program = string;
-            if let Some(token) = ret {
-                sequence.push_back(token);
-                position.push_back(start_at);
-                start_at += width;
-                continue;
+            if lexed.is_none() {
+                lexed = TokenStream::consume_number(rest).map_err(fail)?;
             }
-
-            // consume whitespaces
-            let (string, ret, width) = TokenStream::consume_whitespace(program);
-            program = string;
-            if ret.is_some() {
-                start_at += width;
-                continue;
+            let lexed = lexed
+                .or_else(|| TokenStream::consume_order(rest))
+                .or_else(|| TokenStream::consume_identifier(rest))
+                .or_else(|| TokenStream::consume_operator(rest));
+            match lexed {
+                Some((token, width)) => {
+                    tokens.push(Lexeme {
+                        token,
+                        span: Span {
+                            start: start_at,
+                            end: start_at + width,
+                        },
+                    });
+                    start_at += width;
+                }
+                // fail to lex
+                None => {
+                    let c = rest.chars().next().unwrap_or_default();
+                    let width = c.len_utf8();
+                    return Err(Diagnostic::error(
+                        Code::UnknownCharacter,
+                        &format!("fail to lex: unknown character `{}`.", c),
+                        Span {
+                            start: start_at,
+                            end: start_at + width,
+                        },
+                    ));
+                }
             }
-
-            // fail to lex
-            return Err((format!("fail to lex. left: {}.", program), Byte(start_at)));
         }
-        if sequence.is_empty() {
-            return Err((
-                "fail to lex. need some charactors without whitespace.".to_string(),
-                Byte(start_at),
+        if tokens.is_empty() {
+            return Err(Diagnostic::error(
+                Code::EmptyInput,
+                "fail to lex. need some charactors without whitespace.",
+                Span::at(start_at),
             ));
         }
-        sequence.push_back(Eof);
-        position.push_back(start_at);
-        Ok(Self { sequence, position })
+        tokens.push(Lexeme {
+            token: Eof,
+            span: Span::at(start_at),
+        });
+        Ok(Self {
+            source: program,
+            tokens,
+            cursor: 0,
+        })
+    }
+
+    // the next token, `None` after `Eof` is consumed
+    pub fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.cursor).map(|lexeme| &lexeme.token)
+    }
+
+    // the token after the next one
+    pub fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.cursor + n).map(|lexeme| &lexeme.token)
+    }
+
+    pub fn advance(&mut self) {
+        self.cursor += 1;
+    }
+
+    // bytes of the token at `position` in the source
+    pub fn span(&self, Position(index): Position) -> Span {
+        self.tokens
+            .get(index)
+            .expect("inner error: out of bound")
+            .span
+    }
+
+    // the token at `position` as it is written in the source
+    pub fn text(&self, position: Position) -> &str {
+        let span = self.span(position);
+        &self.source[span.start..span.end]
     }
 
-    fn consume_bracket(buffer: String) -> (String, Option<Token>, usize) {
-        let mut chars = buffer.chars();
-        match chars.by_ref().peekable().peek() {
-            Some(op) if op == &'(' => ({ chars.collect::<String>() }, Some(Reserved(LeftBra)), 1),
-            Some(op) if op == &')' => ({ chars.collect::<String>() }, Some(Reserved(RightBra)), 1),
-            _ => (buffer, None, 0),
+    // `# 12 "file.c"` up to the end of the line with the line and file it gives
+    fn consume_line_marker(rest: &str) -> Option<(usize, usize, Option<String>)> {
+        if !rest.starts_with('#') {
+            return None;
         }
+        let width = rest.find('\n').unwrap_or(rest.len());
+        let (line, file) = line_marker(&rest[..width])?;
+        Some((width, line, file))
     }
 
-    fn consume_semicolon(buffer: String) -> (String, Option<Token>, usize) {
-        if let Some(c) = buffer.chars().next() {
-            if c == ';' {
-                (
-                    buffer.chars().skip(1).collect::<String>(),
-                    Some(SemiColon),
-                    1,
-                )
-            } else {
-                (buffer, None, 0)
+    fn consume_comment(rest: &str) -> Result<usize, LexError> {
+        if rest.starts_with("//") {
+            Ok(rest.find('\n').unwrap_or(rest.len()))
+        } else if let Some(body) = rest.strip_prefix("/*") {
+            match body.find("*/") {
+                Some(end) => Ok(end + 4),
+                None => Err((
+                    Code::UnterminatedComment,
+                    "unterminated comment. need `*/`.",
+                    0,
+                )),
             }
         } else {
-            (buffer, None, 0)
+            Ok(0)
         }
     }
 
-    fn consume_alphabetic(buffer: String) -> (String, Option<Token>, usize) {
-        let alphabets = buffer.chars().take_while(|c| c.is_alphabetic()).count();
-        (
-            buffer.chars().skip(alphabets).collect::<String>(),
-            if alphabets == 0 {
-                None
-            } else {
-                Some(Ident((buffer.chars().next().unwrap() as u8 - b'a') as u64))
-            },
-            alphabets,
-        )
+    // width in bytes as whitespaces may be multi-byte characters
+    fn consume_whitespace(rest: &str) -> usize {
+        rest.find(|c: char| !c.is_whitespace())
+            .unwrap_or(rest.len())
     }
 
-    fn consume_number(buffer: String) -> (String, Option<Token>, usize) {
-        let digit = buffer.chars().take_while(|c| c.is_ascii_digit()).count();
-        let mut chars = buffer.chars();
-        let number = chars.by_ref().take(digit).collect::<String>();
-        let buffer = chars.collect::<String>();
-        (
-            buffer,
-            if number.is_empty() {
-                None
+    // `'a'` or `"abc"`, errors carry the byte offset of the problem
+    fn consume_literal(rest: &str) -> Result<Option<(Token, usize)>, LexError> {
+        let quote = match rest.chars().next() {
+            Some(quote) if quote == '\'' || quote == '"' => quote,
+            _ => return Ok(None),
+        };
+        let mut bytes = Vec::new();
+        let mut width = 1;
+        loop {
+            let c = match rest[width..].chars().next() {
+                Some('\n') | None => {
+                    return Err((
+                        Code::UnterminatedLiteral,
+                        if quote == '"' {
+                            "unterminated string literal. need `\"`."
+                        } else {
+                            "unterminated character literal. need `'`."
+                        },
+                        0,
+                    ))
+                }
+                Some(c) => c,
+            };
+            if c == quote {
+                width += 1;
+                break;
+            }
+            if c == '\\' {
+                let (byte, escape) = TokenStream::escape(&rest[width + 1..]).ok_or((
+                    Code::InvalidEscape,
+                    "invalid escape sequence.",
+                    width,
+                ))?;
+                bytes.push(byte);
+                width += 1 + escape;
             } else {
-                Some(Number(
-                    number.parse::<i64>().expect("fail to parse number."),
-                ))
-            },
-            digit,
-        )
+                let mut encoded = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut encoded).as_bytes());
+                width += c.len_utf8();
+            }
+        }
+        let token = if quote == '"' {
+            StringLiteral(bytes)
+        } else if bytes.len() == 1 {
+            CharLiteral(bytes[0] as i8 as i64)
+        } else {
+            return Err((
+                Code::InvalidCharacterLiteral,
+                "character literal must contain exactly one character.",
+                0,
+            ));
+        };
+        Ok(Some((token, width)))
     }
 
-    fn consume_order(buffer: String) -> (String, Option<Token>, usize) {
-        let mut chars = buffer.chars();
-        match chars.by_ref().peekable().peek() {
-            Some(or) if or == &'<' => match chars.by_ref().peekable().peek() {
-                Some(eq) if eq == &'=' => (chars.collect::<String>(), Some(Reserved(Le)), 2),
-                _ => (
-                    buffer.chars().skip(1).collect::<String>(),
-                    Some(Reserved(Lt)),
-                    1,
-                ),
-            },
-            Some(or) if or == &'>' => match chars.by_ref().peekable().peek() {
-                Some(eq) if eq == &'=' => (chars.collect::<String>(), Some(Reserved(Ge)), 2),
-                _ => (
-                    buffer.chars().skip(1).collect::<String>(),
-                    Some(Reserved(Gt)),
-                    1,
-                ),
-            },
-            Some(or) if or == &'=' => match chars.by_ref().peekable().peek() {
-                Some(eq) if eq == &'=' => (chars.collect::<String>(), Some(Reserved(Eq)), 2),
-                // fail to parse
-                _ => (
-                    buffer.chars().skip(1).collect::<String>(),
-                    Some(Reserved(Assign)),
-                    1,
-                ),
-            },
-            Some(or) if or == &'!' => match chars.by_ref().peekable().peek() {
-                Some(eq) if eq == &'=' => (chars.collect::<String>(), Some(Reserved(Ne)), 2),
-                // fail to parse
-                _ => (buffer, None, 0),
-            },
-            _ => (buffer, None, 0),
+    // the byte of an escape sequence after `\\` and its width
+    fn escape(buffer: &str) -> Option<(u8, usize)> {
+        let c = buffer.chars().next()?;
+        let simple = match c {
+            'n' => Some(b'\n'),
+            't' => Some(b'\t'),
+            'r' => Some(b'\r'),
+            'a' => Some(0x07),
+            'b' => Some(0x08),
+            'f' => Some(0x0c),
+            'v' => Some(0x0b),
+            '\\' | '\'' | '"' | '?' => Some(c as u8),
+            _ => None,
+        };
+        if simple.is_some() {
+            return simple.map(|byte| (byte, 1));
         }
+        let (digits, radix, skip) = match c {
+            '0'..='7' => (
+                buffer.chars().take(3).take_while(|c| c.is_digit(8)).count(),
+                8,
+                0,
+            ),
+            'x' => (
+                buffer[1..]
+                    .chars()
+                    .take_while(|c| c.is_ascii_hexdigit())
+                    .count(),
+                16,
+                1,
+            ),
+            _ => return None,
+        };
+        let value = u32::from_str_radix(&buffer[skip..skip + digits], radix).ok()?;
+        if value > 0xff {
+            return None;
+        }
+        Some((value as u8, skip + digits))
     }
 
-    fn consume_operator(buffer: String) -> (String, Option<Token>, usize) {
-        let mut chars = buffer.chars();
-        match chars.by_ref().peekable().peek() {
-            Some(op) if op == &'+' => ({ chars.collect::<String>() }, Some(Reserved(Add)), 1),
-            Some(op) if op == &'-' => ({ chars.collect::<String>() }, Some(Reserved(Sub)), 1),
-            Some(op) if op == &'*' => ({ chars.collect::<String>() }, Some(Reserved(Mul)), 1),
-            Some(op) if op == &'/' => ({ chars.collect::<String>() }, Some(Reserved(Div)), 1),
-            _ => (buffer, None, 0),
+    // `42`, `0x1F`, `017` or `0b101` with an optional `u`, `l` or `ll` suffix
+    fn consume_number(rest: &str) -> Result<Option<(Token, usize)>, LexError> {
+        match rest.chars().next() {
+            Some(c) if c.is_ascii_digit() => {}
+            _ => return Ok(None),
         }
+        // the whole literal including a wrong suffix like `12abc`
+        let width = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        let literal = &rest[..width];
+        let lower = literal.to_ascii_lowercase();
+        let (radix, start) = if lower.starts_with("0x") {
+            (16, 2)
+        } else if lower.starts_with("0b") {
+            (2, 2)
+        } else if lower.starts_with('0') {
+            (8, 0)
+        } else {
+            (10, 0)
+        };
+        let digits = literal[start..]
+            .chars()
+            .take_while(|c| {
+                if radix == 16 {
+                    c.is_ascii_hexdigit()
+                } else {
+                    c.is_ascii_digit()
+                }
+            })
+            .count();
+        if digits == 0 {
+            return Err((
+                Code::InvalidInteger,
+                "need digits after the prefix of the integer literal.",
+                0,
+            ));
+        }
+        let text = &literal[start..start + digits];
+        if let Some(wrong) = text.chars().position(|c| !c.is_digit(radix)) {
+            return Err((
+                Code::InvalidInteger,
+                "invalid digit in the integer literal.",
+                start + wrong,
+            ));
+        }
+        let value = u64::from_str_radix(text, radix)
+            .map_err(|_| (Code::IntegerOverflow, "integer literal is too large.", 0))?;
+
+        let suffix = &literal[start + digits..];
+        let (unsigned, long) = match suffix.to_ascii_lowercase().as_str() {
+            "" => (false, 0),
+            "u" => (true, 0),
+            "l" => (false, 1),
+            "ul" | "lu" => (true, 1),
+            "ll" => (false, 2),
+            "ull" | "llu" => (true, 2),
+            _ => (false, 3),
+        };
+        // `lL` is not a suffix
+        if long == 3 || suffix.contains("lL") || suffix.contains("Ll") {
+            return Err((
+                Code::InvalidInteger,
+                "invalid suffix on the integer literal.",
+                start + digits,
+            ));
+        }
+        // the first type which can hold the value, decimal literals without
+        // `u` never become unsigned
+        let ty = INTEGER_TYPES
+            .iter()
+            .copied()
+            .filter(|ty| ty.rank() >= long && (!unsigned || ty.is_unsigned()))
+            .filter(|ty| radix != 10 || unsigned || !ty.is_unsigned())
+            .find(|ty| value <= ty.max())
+            .ok_or((
+                Code::IntegerOverflow,
+                "integer literal is too large for its type.",
+                0,
+            ))?;
+        Ok(Some((Number(value as i64, ty), width)))
     }
 
-    fn consume_whitespace(buffer: String) -> (String, Option<Token>, usize) {
-        let spaces = buffer.chars().take_while(|c| c.is_whitespace()).count();
-        (
-            buffer.chars().skip(spaces).collect::<String>(),
-            if spaces == 0 { None } else { Some(Eof) },
-            spaces,
-        )
+    fn consume_semicolon(rest: &str) -> Option<(Token, usize)> {
+        if rest.starts_with(';') {
+            Some((SemiColon, 1))
+        } else {
+            None
+        }
+    }
+
+    fn consume_bracket(rest: &str) -> Option<(Token, usize)> {
+        TokenStream::consume_word(rest, &BRACKETS)
+    }
+
+    fn consume_order(rest: &str) -> Option<(Token, usize)> {
+        TokenStream::consume_word(rest, &ORDERS)
+    }
+
+    fn consume_operator(rest: &str) -> Option<(Token, usize)> {
+        TokenStream::consume_word(rest, &OPERATORS)
+    }
+
+    // the first word of the table which the input starts with
+    fn consume_word(rest: &str, table: &[(&str, Word)]) -> Option<(Token, usize)> {
+        table
+            .iter()
+            .find(|(text, _)| rest.starts_with(text))
+            .map(|&(text, word)| (Reserved(word), text.len()))
+    }
+
+    fn consume_identifier(rest: &str) -> Option<(Token, usize)> {
+        match rest.chars().next() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+            _ => return None,
+        }
+        let width = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        let name = &rest[..width];
+        let token = match KEYWORDS.iter().find(|(keyword, _)| keyword == &name) {
+            Some(&(_, word)) => Reserved(word),
+            None => Ident(name.to_string()),
+        };
+        Some((token, width))
     }
 }
 
@@ -250,6 +621,17 @@ impl TokenStream {
 mod tests_lexer {
     use super::*;
 
+    // tokens and the bytes where they start
+    fn lex(program: String) -> Result<(Vec<Token>, Vec<usize>), Diagnostic> {
+        TokenStream::tokenize(program).map(|stream| {
+            stream
+                .tokens
+                .into_iter()
+                .map(|lexeme| (lexeme.token, lexeme.span.start))
+                .unzip()
+        })
+    }
+
     #[test]
     fn for_tokenize() {
         let cases = vec![
@@ -262,84 +644,390 @@ mod tests_lexer {
         let answers = vec![
             (
                 vec![
-                    Number(5),
+                    Number(5, IntegerType::Int),
                     Reserved(Add),
-                    Number(20),
+                    Number(20, IntegerType::Int),
                     Reserved(Sub),
-                    Number(4),
+                    Number(4, IntegerType::Int),
                     Eof,
                 ],
                 vec![0, 1, 2, 4, 5, 6],
             ),
             (
                 vec![
-                    Number(23),
+                    Number(23, IntegerType::Int),
                     Reserved(Sub),
-                    Number(8),
+                    Number(8, IntegerType::Int),
                     Reserved(Add),
-                    Number(5),
+                    Number(5, IntegerType::Int),
                     Reserved(Sub),
-                    Number(3),
+                    Number(3, IntegerType::Int),
                     Reserved(Add),
-                    Number(56),
+                    Number(56, IntegerType::Int),
                     Reserved(Add),
-                    Number(9),
+                    Number(9, IntegerType::Int),
                     Reserved(Sub),
-                    Number(8),
+                    Number(8, IntegerType::Int),
                     Eof,
                 ],
                 vec![0, 3, 5, 6, 8, 9, 13, 15, 17, 20, 22, 24, 26, 27],
             ),
-            (vec![Number(0), Eof], vec![0, 1]),
+            (vec![Number(0, IntegerType::Int), Eof], vec![0, 1]),
             (
                 vec![
-                    Number(4),
+                    Number(4, IntegerType::Int),
                     Reserved(Mul),
-                    Number(5),
+                    Number(5, IntegerType::Int),
                     Reserved(Lt),
-                    Number(3),
+                    Number(3, IntegerType::Int),
                     Reserved(Sub),
-                    Number(2),
+                    Number(2, IntegerType::Int),
                     Eof,
                 ],
                 vec![0, 2, 4, 6, 8, 10, 12, 13],
             ),
             (
-                vec![Reserved(Sub), Number(3), Reserved(Eq), Number(5), Eof],
+                vec![
+                    Reserved(Sub),
+                    Number(3, IntegerType::Int),
+                    Reserved(Eq),
+                    Number(5, IntegerType::Int),
+                    Eof,
+                ],
                 vec![0, 1, 2, 4, 5],
             ),
         ];
-        for (case, answer) in cases
-            .into_iter()
-            .map(|s| s.to_string())
-            .zip(answers.into_iter().map(|tokens| TokenStream {
-                sequence: tokens.0.into_iter().collect(),
-                position: tokens.1.into_iter().collect(),
-            }))
-        {
-            assert_eq!(TokenStream::tokenize(case), Ok(answer));
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
+            assert_eq!(lex(case), Ok(answer));
         }
         assert_eq!(1 + 2, 3);
     }
 
+    #[test]
+    fn for_tokenize_identifier() {
+        let program = "foo = 1; far_2 =foo+_x;".to_string();
+        let answer = (
+            vec![
+                Ident("foo".to_string()),
+                Reserved(Assign),
+                Number(1, IntegerType::Int),
+                SemiColon,
+                Ident("far_2".to_string()),
+                Reserved(Assign),
+                Ident("foo".to_string()),
+                Reserved(Add),
+                Ident("_x".to_string()),
+                SemiColon,
+                Eof,
+            ],
+            vec![0, 4, 6, 7, 9, 15, 16, 19, 20, 22, 23],
+        );
+        assert_eq!(lex(program), Ok(answer));
+    }
+
+    #[test]
+    fn for_tokenize_keyword() {
+        let program = "return returned;".to_string();
+        let answer = (
+            vec![
+                Reserved(Return),
+                Ident("returned".to_string()),
+                SemiColon,
+                Eof,
+            ],
+            vec![0, 7, 15, 16],
+        );
+        assert_eq!(lex(program), Ok(answer));
+        let program = "if else while for iff".to_string();
+        let answer = (
+            vec![
+                Reserved(If),
+                Reserved(Else),
+                Reserved(While),
+                Reserved(For),
+                Ident("iff".to_string()),
+                Eof,
+            ],
+            vec![0, 3, 8, 14, 18, 21],
+        );
+        assert_eq!(lex(program), Ok(answer));
+    }
+
+    #[test]
+    fn for_tokenize_block() {
+        let program = "{int x,y;}".to_string();
+        let answer = (
+            vec![
+                Reserved(LeftBrace),
+                Reserved(Int),
+                Ident("x".to_string()),
+                Reserved(Comma),
+                Ident("y".to_string()),
+                SemiColon,
+                Reserved(RightBrace),
+                Eof,
+            ],
+            vec![0, 1, 5, 6, 7, 8, 9, 10],
+        );
+        assert_eq!(lex(program), Ok(answer));
+        let program = "char a[2];sizeof a[0]".to_string();
+        let answer = (
+            vec![
+                Reserved(Char),
+                Ident("a".to_string()),
+                Reserved(LeftSquare),
+                Number(2, IntegerType::Int),
+                Reserved(RightSquare),
+                SemiColon,
+                Reserved(Sizeof),
+                Ident("a".to_string()),
+                Reserved(LeftSquare),
+                Number(0, IntegerType::Int),
+                Reserved(RightSquare),
+                Eof,
+            ],
+            vec![0, 5, 6, 7, 8, 9, 10, 17, 18, 19, 20, 21],
+        );
+        assert_eq!(lex(program), Ok(answer));
+    }
+
     #[test]
     fn for_tokenize_panic_empty() {
         let program = " \n   ".to_string();
-        assert_eq!(
-            TokenStream::tokenize(program),
-            Err((
-                "fail to lex. need some charactors without whitespace.".to_string(),
-                Byte(5)
-            ))
+        let error = TokenStream::tokenize(program).unwrap_err();
+        assert_eq!((error.code, error.span), (Code::EmptyInput, Span::at(5)));
+    }
+
+    #[test]
+    fn for_tokenize_multibyte_whitespace() {
+        // U+3000 is three bytes in UTF-8
+        let program = "1\u{3000}+\t2".to_string();
+        let answer = (
+            vec![
+                Number(1, IntegerType::Int),
+                Reserved(Add),
+                Number(2, IntegerType::Int),
+                Eof,
+            ],
+            vec![0, 4, 6, 7],
         );
+        assert_eq!(lex(program), Ok(answer));
     }
 
     #[test]
     fn for_tokenize_panic_invalid() {
         let program = "12 + 2 - ☀︎ + 89".to_string();
+        let error = TokenStream::tokenize(program).unwrap_err();
+        assert_eq!(
+            (error.code, error.span),
+            (Code::UnknownCharacter, Span { start: 9, end: 12 })
+        );
+        // only the offending character is quoted, not the rest of the input
+        assert_eq!(error.message, "fail to lex: unknown character `☀`.");
+    }
+
+    #[test]
+    fn for_tokenize_comment() {
+        let program = "1 // one\n+ /* two\n */2/**/;".to_string();
+        let answer = (
+            vec![
+                Number(1, IntegerType::Int),
+                Reserved(Add),
+                Number(2, IntegerType::Int),
+                SemiColon,
+                Eof,
+            ],
+            vec![0, 9, 21, 26, 27],
+        );
+        assert_eq!(lex(program), Ok(answer));
+        let error = TokenStream::tokenize("1 + /* 2;\n".to_string()).unwrap_err();
+        assert_eq!(
+            (error.code, error.span),
+            (Code::UnterminatedComment, Span::at(4))
+        );
+    }
+
+    #[test]
+    fn for_tokenize_line_marker() {
+        let program = "# 1 \"a.c\"\n  #line 3\n1 # 2".to_string();
+        let error = TokenStream::tokenize(program).unwrap_err();
+        assert_eq!(
+            (error.code, error.span),
+            (Code::UnknownCharacter, Span { start: 22, end: 23 })
+        );
+        let program = "# 1 \"a.c\" 1\n  #line 3\n1;".to_string();
+        let answer = (
+            vec![Number(1, IntegerType::Int), SemiColon, Eof],
+            vec![22, 23, 24],
+        );
+        assert_eq!(lex(program), Ok(answer));
+        // skipped markers are recorded with the end of their line, those in
+        // comments are not markers
+        let mut markers = Vec::new();
+        let program = "# 1 \"a.c\"\n/*\n# 9\n*/\n1;".to_string();
+        TokenStream::tokenize_with_markers(program, &mut markers).unwrap();
+        assert_eq!(markers, vec![(Byte(9), 1, Some("a.c".to_string()))]);
+    }
+
+    #[test]
+    fn for_tokenize_literal() {
+        let program = r#"'a' '\n' '\\' '\'' '\x41' '\101' '\0' '\377' "a\tb\"" "" "é""#.to_string();
+        let answer = (
+            vec![
+                CharLiteral(97),
+                CharLiteral(10),
+                CharLiteral(92),
+                CharLiteral(39),
+                CharLiteral(65),
+                CharLiteral(65),
+                CharLiteral(0),
+                CharLiteral(-1),
+                StringLiteral(b"a\tb\"".to_vec()),
+                StringLiteral(vec![]),
+                StringLiteral("é".as_bytes().to_vec()),
+                Eof,
+            ],
+            vec![0, 4, 9, 14, 19, 26, 33, 38, 45, 54, 57, 61],
+        );
+        assert_eq!(lex(program), Ok(answer));
+    }
+
+    #[test]
+    fn for_tokenize_literal_error() {
+        let cases = vec![
+            "x = 'ab';",
+            "x = '';",
+            "x = 'a;",
+            "x = \"abc\n\";",
+            "x = \"a\\qb\";",
+            "x = '\\x100';",
+            "x = '\\x';",
+        ];
+        let answers = vec![
+            (Code::InvalidCharacterLiteral, 4),
+            (Code::InvalidCharacterLiteral, 4),
+            (Code::UnterminatedLiteral, 4),
+            (Code::UnterminatedLiteral, 4),
+            (Code::InvalidEscape, 6),
+            (Code::InvalidEscape, 5),
+            (Code::InvalidEscape, 5),
+        ];
+        for (case, answer) in cases.into_iter().zip(answers) {
+            let error = TokenStream::tokenize(case.to_string()).unwrap_err();
+            assert_eq!((error.code, error.span.start), answer, "{}", case);
+        }
+    }
+
+    #[test]
+    fn for_tokenize_integer() {
+        let cases = vec![
+            "0x1F",
+            "017",
+            "0b101",
+            "0",
+            "10u",
+            "10L",
+            "10ll",
+            "10uL",
+            "10LLU",
+            "2147483648",
+            "0x80000000",
+            "0x100000000",
+            "0xffffffffffffffff",
+            "4294967295u",
+        ];
+        let answers = vec![
+            Number(31, IntegerType::Int),
+            Number(15, IntegerType::Int),
+            Number(5, IntegerType::Int),
+            Number(0, IntegerType::Int),
+            Number(10, IntegerType::UnsignedInt),
+            Number(10, IntegerType::Long),
+            Number(10, IntegerType::LongLong),
+            Number(10, IntegerType::UnsignedLong),
+            Number(10, IntegerType::UnsignedLongLong),
+            Number(2147483648, IntegerType::Long),
+            Number(2147483648, IntegerType::UnsignedInt),
+            Number(4294967296, IntegerType::Long),
+            Number(-1, IntegerType::UnsignedLong),
+            Number(4294967295, IntegerType::UnsignedInt),
+        ];
+        for (case, answer) in cases.into_iter().zip(answers) {
+            let stream = TokenStream::tokenize(case.to_string()).unwrap();
+            assert_eq!(stream.peek(), Some(&answer), "{}", case);
+        }
+    }
+
+    #[test]
+    fn for_tokenize_integer_error() {
+        let cases = vec![
+            "x = 09;",
+            "x = 0b12;",
+            "x = 0x;",
+            "x = 12abc;",
+            "x = 1lL;",
+            "x = 1uu;",
+            "x = 99999999999999999999;",
+            "x = 9223372036854775808;",
+        ];
+        let answers = vec![
+            (Code::InvalidInteger, 5),
+            (Code::InvalidInteger, 7),
+            (Code::InvalidInteger, 4),
+            (Code::InvalidInteger, 6),
+            (Code::InvalidInteger, 5),
+            (Code::InvalidInteger, 5),
+            (Code::IntegerOverflow, 4),
+            (Code::IntegerOverflow, 4),
+        ];
+        for (case, answer) in cases.into_iter().zip(answers) {
+            let error = TokenStream::tokenize(case.to_string()).unwrap_err();
+            assert_eq!((error.code, error.span.start), answer, "{}", case);
+        }
+    }
+
+    #[test]
+    fn for_tokenize_span() {
+        let stream = TokenStream::tokenize("x <= 0x1F; /* */ \"a\\n\"".to_string()).unwrap();
+        let spans = stream
+            .tokens
+            .iter()
+            .map(|lexeme| (lexeme.span.start, lexeme.span.end))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![(0, 1), (2, 4), (5, 9), (9, 10), (17, 22), (22, 22)]
+        );
+
+        assert_eq!(stream.text(Position(1)), "<=");
+        assert_eq!(stream.text(Position(4)), "\"a\\n\"");
+        assert_eq!(stream.text(Position(5)), "");
+
+        // a long input is lexed in one pass
+        let program = "a = a + 1;\n".repeat(100_000);
+        let stream = TokenStream::tokenize(program).unwrap();
+        assert_eq!(stream.tokens.len(), 600_001);
+        assert_eq!(stream.tokens[600_000].span.start, 1_100_000);
+    }
+
+    #[test]
+    fn for_tokenize_operator() {
+        let program = "a<<=b>>c&&d||!e&f|g^~h%i?j:k++,l--+=-=*=/=%=&=|=^=>>=<<".to_string();
+        let words = TokenStream::tokenize(program)
+            .unwrap()
+            .into_iter()
+            .filter_map(|token| match token {
+                Reserved(word) => Some(*word),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
         assert_eq!(
-            TokenStream::tokenize(program),
-            Err(("fail to lex. left: ☀︎ + 89.".to_string(), Byte(9)))
+            words,
+            vec![
+                ShlAssign, Shr, LogAnd, LogOr, Not, BitAnd, BitOr, BitXor, BitNot, Mod, Question,
+                Colon, Inc, Comma, Dec, AddAssign, SubAssign, MulAssign, DivAssign, ModAssign,
+                AndAssign, OrAssign, XorAssign, ShrAssign, Shl,
+            ]
         );
     }
 }
@@ -1,5 +1,7 @@
+pub mod driver;
 pub mod error;
 pub mod generator;
 pub mod lexer;
 pub mod parser;
-pub mod static_check;
+pub mod semantic_check;
+pub mod type_check;
@@ -1,200 +1,1299 @@
 use crate::error::*;
 use crate::lexer::Token::*;
 use crate::lexer::*;
+use crate::type_check::Type;
+use std::collections::HashMap;
 use std::process;
-use Node::*;
+use NodeKind::*;
+
+#[derive(Debug, Clone, Eq)]
+pub struct Node {
+    pub kind: NodeKind,
+    // the operator of binary nodes, otherwise the first token
+    pub span: Span,
+    // set on expressions by the type checking pass, literals know theirs
+    pub ty: Option<Type>,
+}
+
+// trees compare by their shape wherever they were written
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Node {
+    pub fn new(kind: NodeKind, span: Span) -> Self {
+        Self {
+            kind,
+            span,
+            ty: None,
+        }
+    }
+
+    pub fn ty(&self) -> &Type {
+        self.ty.as_ref().expect("inner error: untyped node")
+    }
+}
 
 #[derive(Debug, Clone, Eq, PartialEq)]
-pub enum Node {
+pub enum NodeKind {
     Add(Box<Node>, Box<Node>),
     Sub(Box<Node>, Box<Node>),
     Mul(Box<Node>, Box<Node>),
     Div(Box<Node>, Box<Node>),
+    Mod(Box<Node>, Box<Node>),
+    Shl(Box<Node>, Box<Node>),
+    Shr(Box<Node>, Box<Node>),
+    BitAnd(Box<Node>, Box<Node>),
+    BitOr(Box<Node>, Box<Node>),
+    BitXor(Box<Node>, Box<Node>),
     Eq(Box<Node>, Box<Node>),
     Ne(Box<Node>, Box<Node>),
     Le(Box<Node>, Box<Node>),
     Lt(Box<Node>, Box<Node>),
+    // the right side is evaluated only when needed
+    LogAnd(Box<Node>, Box<Node>),
+    LogOr(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    BitNot(Box<Node>),
+    // `condition ? then : otherwise`
+    Cond(Box<Node>, Box<Node>, Box<Node>),
+    Comma(Box<Node>, Box<Node>),
     Assign(Box<Node>, Box<Node>),
+    // `a += b` evaluates `a` only once, `++a` is `a += 1`
+    AssignOp(BinaryOp, Box<Node>, Box<Node>),
+    // `a++` and `a--` give the value before the update
+    PostInc(Box<Node>),
+    PostDec(Box<Node>),
+    // conversion to the type of this node
+    Cast(Box<Node>),
+    // `&a` and `*p`
+    Addr(Box<Node>),
+    Deref(Box<Node>),
+    // `sizeof expr`, replaced with the size by the type checking pass
+    Sizeof(Box<Node>),
+    // `return;` has no value
+    Return(Option<Box<Node>>),
+    If(Box<Node>, Box<Node>, Option<Box<Node>>),
+    While(Box<Node>, Box<Node>),
+    For(
+        Option<Box<Node>>,
+        Option<Box<Node>>,
+        Option<Box<Node>>,
+        Box<Node>,
+    ),
+    Block(Vec<Node>),
+    Call {
+        name: String,
+        args: Vec<Node>,
+    },
+    // index into the variables of the function
     LVar(usize),
+    // index into the file-scope variables of the program
+    GVar(usize),
+    // a string literal without its terminating null
+    Str(Vec<u8>),
     Num(i64),
 }
 
-pub fn parser(stream: &mut TokenStream) -> Result<Vec<Node>, (String, Position)> {
+// operators which can be combined with `=`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Shl,
+    Shr,
+    BitAnd,
+    BitOr,
+    BitXor,
+}
+
+const COMPOUND_ASSIGNMENTS: [(Word, BinaryOp); 10] = [
+    (Word::AddAssign, BinaryOp::Add),
+    (Word::SubAssign, BinaryOp::Sub),
+    (Word::MulAssign, BinaryOp::Mul),
+    (Word::DivAssign, BinaryOp::Div),
+    (Word::ModAssign, BinaryOp::Mod),
+    (Word::ShlAssign, BinaryOp::Shl),
+    (Word::ShrAssign, BinaryOp::Shr),
+    (Word::AndAssign, BinaryOp::BitAnd),
+    (Word::OrAssign, BinaryOp::BitOr),
+    (Word::XorAssign, BinaryOp::BitXor),
+];
+
+// a binary operator and the node it makes
+type Operator = (Word, fn(Box<Node>, Box<Node>) -> NodeKind);
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub globals: Vec<GlobalVariable>,
+    // contents of the distinct string literals collected by the type
+    // checking pass, emitted as `.LC<index>`
+    pub strings: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub return_type: Type,
+    // indices into the variables of parameters in order of declaration
+    pub params: Vec<usize>,
+    pub locals: Locals,
+    pub body: Vec<Node>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LocalVariable {
+    pub name: String,
+    pub offset: usize,
+    pub ty: Type,
+    // declaration, or the first use of an undeclared variable
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct GlobalVariable {
+    pub name: String,
+    pub ty: Type,
+    pub init: Option<Initializer>,
+    // contents in `.data` worked out by the type checking pass, a variable
+    // without any goes to `.bss`
+    pub data: Vec<Datum>,
+    pub span: Span,
+}
+
+// `= expr` or `= { init, ... }`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Initializer {
+    Expr(Node),
+    List(Vec<Initializer>, Span),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Datum {
+    // an integer of the given size in bytes
+    Integer(usize, i64),
+    // the address of a global plus an offset in bytes
+    Address(String, i64),
+    // the same for a string literal given by its index
+    Literal(usize, i64),
+    Zero(usize),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Locals {
+    // every variable of the function in order of declaration
+    pub variables: Vec<LocalVariable>,
+    // indices into `variables` visible in each nested scope
+    scopes: Vec<Vec<usize>>,
+    offset: usize,
+    max_offset: usize,
+    // names of the file-scope variables declared so far
+    globals: Vec<String>,
+    // whether an undeclared variable is declared on its first use, which
+    // only statements at the top level of a script do
+    implicit: bool,
+}
+
+impl Default for Locals {
+    fn default() -> Self {
+        Self {
+            variables: Vec::new(),
+            scopes: vec![Vec::new()],
+            offset: 0,
+            max_offset: 0,
+            globals: Vec::new(),
+            implicit: true,
+        }
+    }
+}
+
+impl Locals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // locals of a function which can also refer to `globals`, every
+    // variable has to be declared
+    pub fn with_globals(globals: &[GlobalVariable]) -> Self {
+        Self {
+            globals: globals.iter().map(|global| global.name.clone()).collect(),
+            implicit: false,
+            ..Self::default()
+        }
+    }
+
+    // look up the index of the innermost visible variable
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .copied()
+            .find(|&index| self.variables[index].name == name)
+    }
+
+    // a variable declared in the innermost scope
+    pub fn find_in_scope(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .last()
+            .expect("inner error: no scope")
+            .iter()
+            .copied()
+            .find(|&index| self.variables[index].name == name)
+    }
+
+    // declare a variable in the current scope and return its index, the
+    // variable takes the bytes up to `offset` below rbp
+    pub fn declare(&mut self, name: &str, ty: Type, span: Span) -> usize {
+        self.offset = align_to(self.offset + ty.size(), ty.align());
+        self.max_offset = self.max_offset.max(self.offset);
+        self.variables.push(LocalVariable {
+            name: name.to_string(),
+            offset: self.offset,
+            ty,
+            span,
+        });
+        let index = self.variables.len() - 1;
+        self.scopes
+            .last_mut()
+            .expect("inner error: no scope")
+            .push(index);
+        index
+    }
+
+    // returns the index of a variable, registering an undeclared variable on
+    // first use in the function scope as an int
+    pub fn index_of(&mut self, name: &str, span: Span) -> usize {
+        if let Some(index) = self.find(name) {
+            return index;
+        }
+        let index = self.declare(name, Type::Int, span);
+        self.scopes.last_mut().unwrap().pop();
+        self.scopes[0].push(index);
+        index
+    }
+
+    // a local variable shadows a file-scope one of the same name
+    pub fn variable(&mut self, name: &str, span: Span) -> Result<NodeKind, Diagnostic> {
+        if let Some(index) = self.find(name) {
+            return Ok(LVar(index));
+        }
+        if let Some(index) = self.globals.iter().rposition(|global| global == name) {
+            return Ok(GVar(index));
+        }
+        if !self.implicit {
+            return Err(Diagnostic::error(
+                Code::Undeclared,
+                &format!("fail to parse: use of undeclared identifier `{}`.", name),
+                span,
+            ));
+        }
+        Ok(LVar(self.index_of(name, span)))
+    }
+
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    // free variables of the innermost scope so that their slots can be reused
+    pub fn leave_scope(&mut self) {
+        self.scopes.pop();
+        self.offset = self
+            .scopes
+            .iter()
+            .flatten()
+            .map(|&index| self.variables[index].offset)
+            .max()
+            .unwrap_or(0);
+    }
+
+    // stack size for the deepest nesting of local variables aligned to 16 bytes
+    pub fn stack_size(&self) -> usize {
+        align_to(self.max_offset, 16)
+    }
+}
+
+pub fn align_to(n: usize, align: usize) -> usize {
+    n.div_ceil(align) * align
+}
+
+// parse every function, collecting syntax errors instead of stopping at
+// the first one
+pub fn parser(stream: &mut TokenStream) -> Result<Program, Vec<Diagnostic>> {
+    let mut errors = Vec::new();
+    let mut functions = Vec::new();
+    let mut globals = Vec::new();
+    let script = is_script(stream);
+    // statements written outside of any function make up `main`
     let mut nodes = Vec::new();
-    program(stream, &mut nodes);
-    Ok(nodes)
+    let mut locals = Locals::new();
+    let mut first_statement = None;
+    // where each function is defined
+    let mut defined = HashMap::new();
+    while let Some(token) = stream.peek() {
+        let result = match token {
+            Eof => {
+                break;
+            }
+            _ if is_function(stream) => {
+                let Position(start) = current_position(stream);
+                let span = stream.span(Position(start + specifier_length(stream)));
+                function(stream, &globals, &mut errors).map(|function| {
+                    // the whole definition is parsed, so nothing is skipped
+                    match defined.get(&function.name) {
+                        Some(&previous) => {
+                            errors.push(redefinition(&function.name, span, previous))
+                        }
+                        None => {
+                            defined.insert(function.name.clone(), span);
+                            functions.push(function);
+                        }
+                    }
+                })
+            }
+            _ if token.is_type() && !script => global_declaration(stream, &mut globals),
+            _ => {
+                first_statement.get_or_insert(current_position(stream));
+                stmt(stream, &mut locals, &mut errors).map(|node| nodes.push(node))
+            }
+        };
+        if let Err(error) = result {
+            errors.push(error);
+            synchronize(stream);
+            // nothing is left to close at the top level
+            consume(stream, Reserved(Word::RightBrace));
+        }
+    }
+    // a variable and a function share the name of a symbol
+    for global in &globals {
+        if let Some(&function) = defined.get(&global.name) {
+            let (span, previous) = if function.start < global.span.start {
+                (global.span, function)
+            } else {
+                (function, global.span)
+            };
+            errors.push(redefinition(&global.name, span, previous));
+        }
+    }
+    if let Some(position) = first_statement {
+        if let Some(&main) = defined.get("main") {
+            errors.push(
+                Diagnostic::error(
+                    Code::MainConflict,
+                    "fail to parse: statements outside of a function conflict with main.",
+                    stream.span(position),
+                )
+                .with_label(main, "`main` is defined here")
+                .with_help("move the statements into `main`"),
+            );
+        }
+        functions.push(Function {
+            name: "main".to_string(),
+            return_type: Type::Int,
+            params: Vec::new(),
+            locals,
+            body: nodes,
+        });
+    }
+    if errors.is_empty() {
+        Ok(Program {
+            functions,
+            globals,
+            strings: Vec::new(),
+        })
+    } else {
+        Err(errors)
+    }
 }
 
-fn program(stream: &mut TokenStream, nodes: &mut Vec<Node>) {
-    while let Some(token) = stream.sequence.front() {
+// skip the rest of a broken statement: up to and including `;`, or up to the
+// `}` closing the enclosing block, skipping nested blocks entirely
+fn synchronize(stream: &mut TokenStream) {
+    let mut depth = 0;
+    while let Some(token) = stream.peek() {
         match token {
-            Eof => {
+            Eof => return,
+            SemiColon if depth == 0 => {
+                stream.advance();
+                return;
+            }
+            Reserved(Word::LeftBrace) => depth += 1,
+            Reserved(Word::RightBrace) if depth == 0 => return,
+            Reserved(Word::RightBrace) => {
+                depth -= 1;
+                if depth == 0 {
+                    stream.advance();
+                    return;
+                }
+            }
+            _ => {}
+        }
+        stream.advance();
+    }
+}
+
+// a file with statements outside of functions is the body of `main`, and
+// declarations among them are its local variables
+fn is_script(stream: &TokenStream) -> bool {
+    let mut n = 0;
+    let mut depth = 0;
+    // at the start of a top-level item
+    let mut start = true;
+    // the outermost braces are a function body, not an initializer which
+    // is followed by `,` or `;`
+    let mut body = false;
+    let mut previous = None;
+    while let Some(token) = stream.peek_nth(n) {
+        match token {
+            Eof => return false,
+            SemiColon if depth == 0 => start = true,
+            _ if start && !token.is_type() => return true,
+            Reserved(Word::LeftBrace) => {
+                if depth == 0 {
+                    body = previous != Some(&Reserved(Word::Assign));
+                }
+                depth += 1;
+            }
+            Reserved(Word::RightBrace) => {
+                depth -= 1;
+                start = depth == 0 && body;
+            }
+            _ => start = false,
+        }
+        previous = Some(token);
+        n += 1;
+    }
+    false
+}
+
+// number of type words and `*` before the name of a declaration
+fn specifier_length(stream: &TokenStream) -> usize {
+    let mut n = 0;
+    while stream.peek_nth(n).is_some_and(Token::is_type) {
+        n += 1;
+    }
+    while let Some(Reserved(Word::Mul)) = stream.peek_nth(n) {
+        n += 1;
+    }
+    n
+}
+
+// `type *ident (` starts a function definition
+fn is_function(stream: &TokenStream) -> bool {
+    let n = specifier_length(stream);
+    n > 0
+        && matches!(
+            (stream.peek_nth(n), stream.peek_nth(n + 1)),
+            (Some(Ident(_)), Some(Reserved(Word::LeftBra)))
+        )
+}
+
+fn current_position(stream: &TokenStream) -> Position {
+    Position(stream.cursor)
+}
+
+fn current_span(stream: &TokenStream) -> Span {
+    stream.span(current_position(stream))
+}
+
+fn binary(kind: fn(Box<Node>, Box<Node>) -> NodeKind, lhs: Node, rhs: Node, span: Span) -> Node {
+    Node::new(kind(Box::new(lhs), Box::new(rhs)), span)
+}
+
+// `type *ident ( (type *ident (, type *ident)*)? ) { stmt* }`
+fn function(
+    stream: &mut TokenStream,
+    globals: &[GlobalVariable],
+    errors: &mut Vec<Diagnostic>,
+) -> Result<Function, Diagnostic> {
+    let mut locals = Locals::with_globals(globals);
+    let ty = specifier(stream)?;
+    let return_type = pointers(stream, ty);
+    let name = identifier(stream)?;
+    expect(stream, Reserved(Word::LeftBra))?;
+    let mut params = Vec::new();
+    // `(void)` takes no parameters
+    if let (Some(Reserved(Word::Void)), Some(Reserved(Word::RightBra))) =
+        (stream.peek(), stream.peek_nth(1))
+    {
+        stream.advance();
+    }
+    if !consume(stream, Reserved(Word::RightBra)) {
+        loop {
+            params.push(variable(stream, &mut locals)?);
+            if consume(stream, Reserved(Word::RightBra)) {
                 break;
             }
-            _ => nodes.push(stmt(stream)),
+            expect(stream, Reserved(Word::Comma))?;
         }
     }
+    // parameters share the scope of the outermost block
+    let body = block(stream, &mut locals, errors)?;
+    Ok(Function {
+        name,
+        return_type,
+        params,
+        locals,
+        body,
+    })
 }
 
-fn stmt(stream: &mut TokenStream) -> Node {
-    let node = expr(stream);
-    match stream.sequence.front() {
-        Some(SemiColon) => {
-            stream.sequence.pop_front();
+// `{ stmt* }` without opening a new scope, a broken statement is recorded
+// and skipped so that the following ones are still checked
+fn block(
+    stream: &mut TokenStream,
+    locals: &mut Locals,
+    errors: &mut Vec<Diagnostic>,
+) -> Result<Vec<Node>, Diagnostic> {
+    let open = current_position(stream);
+    expect(stream, Reserved(Word::LeftBrace))?;
+    let mut nodes = Vec::new();
+    while !consume(stream, Reserved(Word::RightBrace)) {
+        if let Some(Eof) = stream.peek() {
+            return Err(Diagnostic::error(
+                Code::UnmatchedBrace,
+                "fail to parse: this brace doesn't match.",
+                stream.span(open),
+            )
+            .with_help("add `}` at the end of the block"));
+        }
+        match stmt(stream, locals, errors) {
+            Ok(node) => nodes.push(node),
+            Err(error) => {
+                errors.push(error);
+                synchronize(stream);
+            }
+        }
+    }
+    Ok(nodes)
+}
+
+fn stmt(
+    stream: &mut TokenStream,
+    locals: &mut Locals,
+    errors: &mut Vec<Diagnostic>,
+) -> Result<Node, Diagnostic> {
+    let span = current_span(stream);
+    match stream.peek() {
+        Some(Reserved(Word::LeftBrace)) => {
+            locals.enter_scope();
+            let nodes = block(stream, locals, errors);
+            locals.leave_scope();
+            Ok(Node::new(Block(nodes?), span))
+        }
+        Some(token) if token.is_type() => declaration(stream, locals),
+        Some(Reserved(Word::Return)) => {
+            stream.advance();
+            if consume(stream, SemiColon) {
+                return Ok(Node::new(Return(None), span));
+            }
+            let node = Node::new(Return(Some(Box::new(expr(stream, locals)?))), span);
+            expect(stream, SemiColon)?;
+            Ok(node)
+        }
+        Some(Reserved(Word::If)) => {
+            stream.advance();
+            expect(stream, Reserved(Word::LeftBra))?;
+            let condition = expr(stream, locals)?;
+            expect(stream, Reserved(Word::RightBra))?;
+            let then = stmt(stream, locals, errors)?;
+            let otherwise = if consume(stream, Reserved(Word::Else)) {
+                Some(Box::new(stmt(stream, locals, errors)?))
+            } else {
+                None
+            };
+            Ok(Node::new(
+                If(Box::new(condition), Box::new(then), otherwise),
+                span,
+            ))
+        }
+        Some(Reserved(Word::While)) => {
+            stream.advance();
+            expect(stream, Reserved(Word::LeftBra))?;
+            let condition = expr(stream, locals)?;
+            expect(stream, Reserved(Word::RightBra))?;
+            let body = stmt(stream, locals, errors)?;
+            Ok(Node::new(While(Box::new(condition), Box::new(body)), span))
+        }
+        Some(Reserved(Word::For)) => {
+            stream.advance();
+            // a variable declared in the header lives until the end of the loop
+            locals.enter_scope();
+            let node = for_stmt(stream, locals, errors, span);
+            locals.leave_scope();
             node
         }
-        _ => unreachable!(),
+        _ => {
+            let node = expr(stream, locals)?;
+            expect(stream, SemiColon)?;
+            Ok(node)
+        }
+    }
+}
+
+// `( init? ; condition? ; step? ) stmt` following `for`
+fn for_stmt(
+    stream: &mut TokenStream,
+    locals: &mut Locals,
+    errors: &mut Vec<Diagnostic>,
+    span: Span,
+) -> Result<Node, Diagnostic> {
+    expect(stream, Reserved(Word::LeftBra))?;
+    let init = if stream.peek().is_some_and(Token::is_type) {
+        Some(Box::new(declaration(stream, locals)?))
+    } else {
+        optional_expr(stream, locals, SemiColon)?
+    };
+    let condition = optional_expr(stream, locals, SemiColon)?;
+    let step = optional_expr(stream, locals, Reserved(Word::RightBra))?;
+    let body = stmt(stream, locals, errors)?;
+    Ok(Node::new(For(init, condition, step, Box::new(body)), span))
+}
+
+// `int x;` or `int *x = expr;`, initializers become assignments
+fn declaration(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    let span = current_span(stream);
+    let base = specifier(stream)?;
+    let mut nodes = Vec::new();
+    loop {
+        let ty = pointers(stream, base.clone());
+        let name_span = current_span(stream);
+        let index = declare(stream, locals, ty, false)?;
+        let assign_span = current_span(stream);
+        // `,` separates declarators so an initializer is not a comma expression
+        if consume(stream, Reserved(Word::Assign)) {
+            let variable = Node::new(LVar(index), name_span);
+            nodes.push(binary(
+                Assign,
+                variable,
+                assign(stream, locals)?,
+                assign_span,
+            ));
+        }
+        if !consume(stream, Reserved(Word::Comma)) {
+            break;
+        }
+    }
+    expect(stream, SemiColon)?;
+    Ok(Node::new(Block(nodes), span))
+}
+
+// `int x, *p = &x, a[2] = {1, 2};` at file scope
+fn global_declaration(
+    stream: &mut TokenStream,
+    globals: &mut Vec<GlobalVariable>,
+) -> Result<(), Diagnostic> {
+    let base = specifier(stream)?;
+    loop {
+        let ty = pointers(stream, base.clone());
+        let span = current_span(stream);
+        let (name, ty) = declarator(stream, ty)?;
+        if let Some(previous) = globals.iter().find(|global| global.name == name) {
+            return Err(redefinition(&name, span, previous.span));
+        }
+        // the scope of a global starts at the end of its declarator so that
+        // it is visible in its own initializer even if the initializer fails
+        globals.push(GlobalVariable {
+            name,
+            ty,
+            init: None,
+            data: Vec::new(),
+            span,
+        });
+        if consume(stream, Reserved(Word::Assign)) {
+            let mut locals = Locals::with_globals(globals);
+            let init = initializer(stream, &mut locals)?;
+            globals.last_mut().unwrap().init = Some(init);
+        }
+        if !consume(stream, Reserved(Word::Comma)) {
+            break;
+        }
+    }
+    expect(stream, SemiColon)
+}
+
+// an expression or a list of initializers in braces which may end with `,`
+fn initializer(stream: &mut TokenStream, locals: &mut Locals) -> Result<Initializer, Diagnostic> {
+    let span = current_span(stream);
+    if !consume(stream, Reserved(Word::LeftBrace)) {
+        return Ok(Initializer::Expr(assign(stream, locals)?));
+    }
+    let mut items = Vec::new();
+    while !consume(stream, Reserved(Word::RightBrace)) {
+        items.push(initializer(stream, locals)?);
+        if !consume(stream, Reserved(Word::Comma)) {
+            expect(stream, Reserved(Word::RightBrace))?;
+            break;
+        }
+    }
+    Ok(Initializer::List(items, span))
+}
+
+// `void`, `char`, `short`, `int` and `long` with `signed` or `unsigned` in
+// any order such as `long unsigned int`
+fn specifier(stream: &mut TokenStream) -> Result<Type, Diagnostic> {
+    let Position(start) = current_position(stream);
+    // how many times void, char, short, int, long, signed and unsigned appear
+    let mut counts = [0; 7];
+    while let Some(Reserved(word)) = stream.peek() {
+        let index = match word {
+            Word::Void => 0,
+            Word::Char => 1,
+            Word::Short => 2,
+            Word::Int => 3,
+            Word::Long => 4,
+            Word::Signed => 5,
+            Word::Unsigned => 6,
+            _ => break,
+        };
+        counts[index] += 1;
+        stream.advance();
+    }
+    let ty = match counts {
+        [0, 0, 0, 0, 0, 0, 0] => {
+            return Err(Diagnostic::error(
+                Code::NeedType,
+                "fail to parse: need type here.",
+                stream.span(Position(start)),
+            ))
+        }
+        [1, 0, 0, 0, 0, 0, 0] => Some(Type::Void),
+        [0, 1, 0, 0, 0, _, _] => Some(Type::Char),
+        [0, 0, 1, 0..=1, 0, _, _] => Some(Type::Short),
+        [0, 0, 0, 0..=1, 0, _, _] => Some(Type::Int),
+        [0, 0, 0, 0..=1, 1..=2, _, _] => Some(Type::Long),
+        _ => None,
+    };
+    match (ty, counts[5], counts[6]) {
+        (Some(ty), 0, 0) | (Some(ty), 1, 0) => Ok(ty),
+        (Some(ty), 0, 1) => Ok(ty.to_unsigned()),
+        _ => {
+            let last = stream.span(Position(stream.cursor - 1));
+            Err(Diagnostic::error(
+                Code::InvalidType,
+                "fail to parse: invalid combination of type specifiers.",
+                Span {
+                    start: stream.span(Position(start)).start,
+                    end: last.end,
+                },
+            ))
+        }
+    }
+}
+
+// `*` of each declarator such as `int *p, **q;`
+fn pointers(stream: &mut TokenStream, mut ty: Type) -> Type {
+    while consume(stream, Reserved(Word::Mul)) {
+        ty = Type::pointer_to(ty);
+    }
+    ty
+}
+
+// `[n]*` after the name, `int a[2][3]` is an array of two `int[3]`
+fn array_suffix(stream: &mut TokenStream, ty: Type) -> Result<Type, Diagnostic> {
+    let mut lengths = Vec::new();
+    while consume(stream, Reserved(Word::LeftSquare)) {
+        match stream.peek() {
+            Some(&Number(length, _)) if length >= 0 => {
+                stream.advance();
+                lengths.push(length as usize);
+            }
+            _ => {
+                return Err(Diagnostic::error(
+                    Code::NeedNumber,
+                    "fail to parse: need length of array here.",
+                    current_span(stream),
+                ))
+            }
+        }
+        expect(stream, Reserved(Word::RightSquare))?;
+    }
+    Ok(lengths.into_iter().rev().fold(ty, Type::array_of))
+}
+
+// `type *` and `[n]` without a name as in `sizeof(int *[2])`
+fn type_name(stream: &mut TokenStream) -> Result<Type, Diagnostic> {
+    let ty = specifier(stream)?;
+    let ty = pointers(stream, ty);
+    array_suffix(stream, ty)
+}
+
+// `type *ident [n]` of a parameter, an array is passed as a pointer
+fn variable(stream: &mut TokenStream, locals: &mut Locals) -> Result<usize, Diagnostic> {
+    let ty = specifier(stream)?;
+    let ty = pointers(stream, ty);
+    declare(stream, locals, ty, true)
+}
+
+// declare the variable named by the next identifier
+fn declare(
+    stream: &mut TokenStream,
+    locals: &mut Locals,
+    ty: Type,
+    parameter: bool,
+) -> Result<usize, Diagnostic> {
+    let span = current_span(stream);
+    let (name, mut ty) = declarator(stream, ty)?;
+    // an outer one is shadowed instead
+    if let Some(index) = locals.find_in_scope(&name) {
+        return Err(redefinition(&name, span, locals.variables[index].span));
+    }
+    if parameter {
+        ty = ty.decay();
+    }
+    Ok(locals.declare(&name, ty, span))
+}
+
+fn redefinition(name: &str, span: Span, previous: Span) -> Diagnostic {
+    Diagnostic::error(
+        Code::Redefinition,
+        &format!("fail to parse: redefinition of `{}`.", name),
+        span,
+    )
+    .with_label(previous, "previous definition is here")
+}
+
+// the name and the type of a variable, which can't be void
+fn declarator(stream: &mut TokenStream, ty: Type) -> Result<(String, Type), Diagnostic> {
+    let span = current_span(stream);
+    let name = identifier(stream)?;
+    let ty = array_suffix(stream, ty)?;
+    let mut element = &ty;
+    while let Type::Array(base, _) = element {
+        element = base;
+    }
+    if element == &Type::Void {
+        return Err(Diagnostic::error(
+            Code::InvalidType,
+            &format!(
+                "fail to parse: variable `{}` has incomplete type void.",
+                name
+            ),
+            span,
+        ));
+    }
+    Ok((name, ty))
+}
+
+// parse an expression which may be omitted before `terminator`
+fn optional_expr(
+    stream: &mut TokenStream,
+    locals: &mut Locals,
+    terminator: Token,
+) -> Result<Option<Box<Node>>, Diagnostic> {
+    if consume(stream, terminator.clone()) {
+        return Ok(None);
+    }
+    let node = expr(stream, locals)?;
+    expect(stream, terminator)?;
+    Ok(Some(Box::new(node)))
+}
+
+fn consume(stream: &mut TokenStream, token: Token) -> bool {
+    if stream.peek() == Some(&token) {
+        stream.advance();
+        true
+    } else {
+        false
+    }
+}
+
+fn identifier(stream: &mut TokenStream) -> Result<String, Diagnostic> {
+    let position = current_position(stream);
+    match stream.peek() {
+        Some(Ident(name)) => {
+            let name = name.clone();
+            stream.advance();
+            Ok(name)
+        }
+        _ => Err(Diagnostic::error(
+            Code::NeedIdentifier,
+            "fail to parse: need identifier here.",
+            stream.span(position),
+        )),
     }
 }
 
-pub fn expr(stream: &mut TokenStream) -> Node {
-    assign(stream)
+fn expect(stream: &mut TokenStream, token: Token) -> Result<(), Diagnostic> {
+    let position = current_position(stream);
+    if consume(stream, token.clone()) {
+        Ok(())
+    } else {
+        let (code, name) = describe(&token);
+        Err(Diagnostic::error(
+            code,
+            &format!("fail to parse: need {} here.", name),
+            stream.span(position),
+        ))
+    }
+}
+
+// code and name of a missing token used in messages
+fn describe(token: &Token) -> (Code, &'static str) {
+    match token {
+        SemiColon => (Code::NeedSemicolon, "semicolon"),
+        Reserved(Word::LeftBra) | Reserved(Word::RightBra) => (Code::NeedBracket, "bracket"),
+        Reserved(Word::LeftBrace) | Reserved(Word::RightBrace) => (Code::NeedBrace, "brace"),
+        Reserved(Word::LeftSquare) | Reserved(Word::RightSquare) => (Code::NeedBracket, "bracket"),
+        Reserved(Word::Comma) => (Code::NeedComma, "comma"),
+        Reserved(Word::Colon) => (Code::NeedColon, "colon"),
+        Reserved(Word::Int) => (Code::NeedType, "type"),
+        Ident(_) => (Code::NeedIdentifier, "identifier"),
+        Number(_, _) | CharLiteral(_) | StringLiteral(_) => (Code::NeedNumber, "number"),
+        _ => (Code::NeedOperator, "operator"),
+    }
 }
 
-fn assign(stream: &mut TokenStream) -> Node {
-    let mut node = equality(stream);
-    if let Some(Reserved(Word::Assign)) = stream.sequence.front() {
-        stream.sequence.pop_front();
-        node = Assign(Box::new(node), Box::new(assign(stream)))
+pub fn expr(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    let mut node = assign(stream, locals)?;
+    while let Some(Reserved(Word::Comma)) = stream.peek() {
+        let span = current_span(stream);
+        stream.advance();
+        node = binary(Comma, node, assign(stream, locals)?, span);
     }
-    node
+    Ok(node)
+}
+
+fn assign(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    let mut node = conditional(stream, locals)?;
+    let span = current_span(stream);
+    let operator = match stream.peek() {
+        Some(Reserved(Word::Assign)) => None,
+        Some(Reserved(word)) => match COMPOUND_ASSIGNMENTS.iter().find(|(op, _)| op == word) {
+            Some(&(_, operator)) => Some(operator),
+            None => return Ok(node),
+        },
+        _ => return Ok(node),
+    };
+    assignable(&node, span)?;
+    stream.advance();
+    let right = assign(stream, locals)?;
+    node = match operator {
+        Some(operator) => Node::new(AssignOp(operator, Box::new(node), Box::new(right)), span),
+        None => binary(Assign, node, right, span),
+    };
+    Ok(node)
+}
+
+// variables and dereferenced pointers have an address
+fn assignable(node: &Node, span: Span) -> Result<(), Diagnostic> {
+    if matches!(node.kind, LVar(_) | GVar(_) | Deref(_)) {
+        Ok(())
+    } else {
+        Err(Diagnostic::error(
+            Code::NotAssignable,
+            "fail to parse: left value is needed here.",
+            span,
+        ))
+    }
+}
+
+fn conditional(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    let node = log_or(stream, locals)?;
+    let span = current_span(stream);
+    if !consume(stream, Reserved(Word::Question)) {
+        return Ok(node);
+    }
+    let then = expr(stream, locals)?;
+    expect(stream, Reserved(Word::Colon))?;
+    let otherwise = conditional(stream, locals)?;
+    Ok(Node::new(
+        Cond(Box::new(node), Box::new(then), Box::new(otherwise)),
+        span,
+    ))
+}
+
+// `next (op next)*` for the operators of one precedence level
+fn left_associative(
+    stream: &mut TokenStream,
+    locals: &mut Locals,
+    next: fn(&mut TokenStream, &mut Locals) -> Result<Node, Diagnostic>,
+    operators: &[Operator],
+) -> Result<Node, Diagnostic> {
+    let mut node = next(stream, locals)?;
+    while let Some(Reserved(word)) = stream.peek() {
+        let kind = match operators.iter().find(|(op, _)| op == word) {
+            Some(&(_, kind)) => kind,
+            None => break,
+        };
+        let span = current_span(stream);
+        stream.advance();
+        node = binary(kind, node, next(stream, locals)?, span);
+    }
+    Ok(node)
+}
+
+fn log_or(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    left_associative(stream, locals, log_and, &[(Word::LogOr, LogOr)])
+}
+
+fn log_and(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    left_associative(stream, locals, bit_or, &[(Word::LogAnd, LogAnd)])
+}
+
+fn bit_or(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    left_associative(stream, locals, bit_xor, &[(Word::BitOr, BitOr)])
+}
+
+fn bit_xor(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    left_associative(stream, locals, bit_and, &[(Word::BitXor, BitXor)])
+}
+
+fn bit_and(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    left_associative(stream, locals, equality, &[(Word::BitAnd, BitAnd)])
 }
 
-fn equality(stream: &mut TokenStream) -> Node {
-    let mut node = relational(stream);
-    while let Some(token) = stream.sequence.front() {
+fn equality(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    let mut node = relational(stream, locals)?;
+    while let Some(token) = stream.peek() {
+        let span = current_span(stream);
         match token {
             Reserved(eq) if eq == &Word::Eq => {
-                stream.sequence.pop_front();
-                node = Eq(Box::new(node), Box::new(relational(stream)));
+                stream.advance();
+                node = binary(Eq, node, relational(stream, locals)?, span);
             }
             Reserved(ne) if ne == &Word::Ne => {
-                stream.sequence.pop_front();
-                node = Ne(Box::new(node), Box::new(relational(stream)));
+                stream.advance();
+                node = binary(Ne, node, relational(stream, locals)?, span);
             }
             Eof => {
                 break;
             }
-            _ => return node,
+            _ => return Ok(node),
         }
     }
-    node
+    Ok(node)
 }
 
-fn relational(stream: &mut TokenStream) -> Node {
-    let mut node = add(stream);
-    while let Some(token) = stream.sequence.front() {
+fn relational(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    let mut node = shift(stream, locals)?;
+    while let Some(token) = stream.peek() {
+        let span = current_span(stream);
         match token {
             Reserved(le) if le == &Word::Le => {
-                stream.sequence.pop_front();
-                node = Le(Box::new(node), Box::new(add(stream)));
+                stream.advance();
+                node = binary(Le, node, shift(stream, locals)?, span);
             }
             Reserved(lt) if lt == &Word::Lt => {
-                stream.sequence.pop_front();
-                node = Lt(Box::new(node), Box::new(add(stream)));
+                stream.advance();
+                node = binary(Lt, node, shift(stream, locals)?, span);
             }
             Reserved(ge) if ge == &Word::Ge => {
-                stream.sequence.pop_front();
-                node = Le(Box::new(add(stream)), Box::new(node));
+                stream.advance();
+                node = binary(Le, shift(stream, locals)?, node, span);
             }
             Reserved(gt) if gt == &Word::Gt => {
-                stream.sequence.pop_front();
-                node = Lt(Box::new(add(stream)), Box::new(node));
+                stream.advance();
+                node = binary(Lt, shift(stream, locals)?, node, span);
             }
             Eof => {
                 break;
             }
-            _ => return node,
+            _ => return Ok(node),
         }
     }
-    node
+    Ok(node)
+}
+
+fn shift(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    left_associative(stream, locals, add, &[(Word::Shl, Shl), (Word::Shr, Shr)])
 }
 
-pub fn add(stream: &mut TokenStream) -> Node {
-    // println!("e: {:?}", stream.sequence);
-    let mut node = mul(stream);
-    while let Some(token) = stream.sequence.front() {
+pub fn add(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    // println!("e: {:?}", stream.peek());
+    let mut node = mul(stream, locals)?;
+    while let Some(token) = stream.peek() {
+        let span = current_span(stream);
         match token {
             Reserved(op) if op == &Word::Add => {
-                stream.sequence.pop_front();
-                node = Add(Box::new(node), Box::new(mul(stream)))
+                stream.advance();
+                node = binary(Add, node, mul(stream, locals)?, span)
             }
             Reserved(op) if op == &Word::Sub => {
-                stream.sequence.pop_front();
-                node = Sub(Box::new(node), Box::new(mul(stream)))
+                stream.advance();
+                node = binary(Sub, node, mul(stream, locals)?, span)
             }
             Eof => {
                 break;
             }
-            _ => return node,
+            _ => return Ok(node),
         }
     }
-    node
+    Ok(node)
 }
 
-fn mul(stream: &mut TokenStream) -> Node {
-    // println!("m: {:?}", stream.sequence);
-    let mut node = unary(stream);
-    while let Some(token) = stream.sequence.front() {
+fn mul(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    // println!("m: {:?}", stream.peek());
+    let mut node = unary(stream, locals)?;
+    while let Some(token) = stream.peek() {
+        let span = current_span(stream);
         match token {
             Reserved(op) if op == &Word::Mul => {
-                stream.sequence.pop_front();
-                node = Mul(Box::new(node), Box::new(unary(stream)))
+                stream.advance();
+                node = binary(Mul, node, unary(stream, locals)?, span)
             }
             Reserved(op) if op == &Word::Div => {
-                stream.sequence.pop_front();
-                node = Div(Box::new(node), Box::new(unary(stream)))
+                stream.advance();
+                node = binary(Div, node, unary(stream, locals)?, span)
+            }
+            Reserved(op) if op == &Word::Mod => {
+                stream.advance();
+                node = binary(Mod, node, unary(stream, locals)?, span)
             }
             Eof => {
                 break;
             }
-            _ => return node,
+            _ => return Ok(node),
         }
     }
-    node
+    Ok(node)
 }
 
-fn unary(stream: &mut TokenStream) -> Node {
-    // println!("u: {:?}", stream.sequence);
-    if let Some(token) = stream.sequence.front() {
-        match token {
-            Reserved(op) if op == &Word::Add => {
-                stream.sequence.pop_front();
-                primary(stream)
-            }
-            Reserved(op) if op == &Word::Sub => {
-                stream.sequence.pop_front();
-                Sub(Box::new(Num(0)), Box::new(primary(stream)))
+// `("+" | "-" | "!" | "~" | "++" | "--" | "&" | "*" | "sizeof") unary | postfix`
+// or `sizeof ( type )`
+fn unary(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    // println!("u: {:?}", stream.peek());
+    let span = current_span(stream);
+    match stream.peek() {
+        Some(Reserved(op)) if op == &Word::Add => {
+            stream.advance();
+            unary(stream, locals)
+        }
+        Some(Reserved(op)) if op == &Word::Sub => {
+            stream.advance();
+            let zero = Node::new(Num(0), span);
+            Ok(binary(Sub, zero, unary(stream, locals)?, span))
+        }
+        Some(Reserved(op)) if op == &Word::Not => {
+            stream.advance();
+            Ok(Node::new(Not(Box::new(unary(stream, locals)?)), span))
+        }
+        Some(Reserved(op)) if op == &Word::BitNot => {
+            stream.advance();
+            Ok(Node::new(BitNot(Box::new(unary(stream, locals)?)), span))
+        }
+        Some(Reserved(op)) if op == &Word::BitAnd => {
+            stream.advance();
+            let node = unary(stream, locals)?;
+            assignable(&node, span)?;
+            Ok(Node::new(Addr(Box::new(node)), span))
+        }
+        Some(Reserved(op)) if op == &Word::Mul => {
+            stream.advance();
+            Ok(Node::new(Deref(Box::new(unary(stream, locals)?)), span))
+        }
+        Some(Reserved(op)) if op == &Word::Sizeof => {
+            stream.advance();
+            let is_type_name = stream.peek() == Some(&Reserved(Word::LeftBra))
+                && stream.peek_nth(1).is_some_and(Token::is_type);
+            if !is_type_name {
+                return Ok(Node::new(Sizeof(Box::new(unary(stream, locals)?)), span));
             }
-            _ => primary(stream),
+            stream.advance();
+            let ty = type_name(stream)?;
+            expect(stream, Reserved(Word::RightBra))?;
+            // the size is known already
+            let mut node = Node::new(Num(ty.size() as i64), span);
+            node.ty = Some(Type::UnsignedLong);
+            Ok(node)
         }
-    } else {
-        unreachable!()
+        Some(Reserved(op)) if op == &Word::Inc || op == &Word::Dec => {
+            let operator = if op == &Word::Inc {
+                BinaryOp::Add
+            } else {
+                BinaryOp::Sub
+            };
+            stream.advance();
+            let node = unary(stream, locals)?;
+            assignable(&node, span)?;
+            let one = Node::new(Num(1), span);
+            Ok(Node::new(
+                AssignOp(operator, Box::new(node), Box::new(one)),
+                span,
+            ))
+        }
+        _ => postfix(stream, locals),
     }
 }
 
-fn primary(stream: &mut TokenStream) -> Node {
-    // println!("p: {:?}", stream.sequence);
-    if let Some(token) = stream.sequence.pop_front() {
-        match token {
-            Reserved(Word::LeftBra) => {
-                let node = expr(stream);
-                if let Some(token) = stream.sequence.pop_front() {
-                    if token == Reserved(Word::RightBra) {
-                        node
-                    } else {
-                        unreachable!()
+// `primary ("[" expr "]" | "++" | "--")*`
+fn postfix(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    let mut node = primary(stream, locals)?;
+    loop {
+        let span = current_span(stream);
+        // `a[i]` is `*(a + i)`
+        if consume(stream, Reserved(Word::LeftSquare)) {
+            let index = expr(stream, locals)?;
+            expect(stream, Reserved(Word::RightSquare))?;
+            let address = binary(Add, node, index, span);
+            node = Node::new(Deref(Box::new(address)), span);
+            continue;
+        }
+        let kind: fn(Box<Node>) -> NodeKind = match stream.peek() {
+            Some(Reserved(Word::Inc)) => PostInc,
+            Some(Reserved(Word::Dec)) => PostDec,
+            _ => return Ok(node),
+        };
+        assignable(&node, span)?;
+        stream.advance();
+        node = Node::new(kind(Box::new(node)), span);
+    }
+}
+
+fn primary(stream: &mut TokenStream, locals: &mut Locals) -> Result<Node, Diagnostic> {
+    // println!("p: {:?}", stream.peek());
+    let span = current_span(stream);
+    match stream.peek() {
+        Some(Reserved(Word::LeftBra)) => {
+            stream.advance();
+            let node = expr(stream, locals)?;
+            expect(stream, Reserved(Word::RightBra))?;
+            Ok(node)
+        }
+        Some(&Number(number, ty)) => {
+            stream.advance();
+            let mut node = Node::new(Num(number), span);
+            node.ty = Some(Type::from(ty));
+            Ok(node)
+        }
+        // a char literal is an int in C
+        Some(&CharLiteral(number)) => {
+            stream.advance();
+            Ok(Node::new(Num(number), span))
+        }
+        Some(StringLiteral(bytes)) => {
+            let node = Node::new(Str(bytes.clone()), span);
+            stream.advance();
+            Ok(node)
+        }
+        Some(Ident(_)) => {
+            let name = identifier(stream)?;
+            if !consume(stream, Reserved(Word::LeftBra)) {
+                return Ok(Node::new(locals.variable(&name, span)?, span));
+            }
+            let mut args = Vec::new();
+            if !consume(stream, Reserved(Word::RightBra)) {
+                loop {
+                    args.push(assign(stream, locals)?);
+                    if consume(stream, Reserved(Word::RightBra)) {
+                        break;
                     }
-                } else {
-                    unreachable!()
+                    expect(stream, Reserved(Word::Comma))?;
                 }
             }
-            Number(number) => Num(number),
-            Ident(id) => LVar((id + 1) as usize * 8),
-            _ => unreachable!(),
+            Ok(Node::new(Call { name, args }, span))
         }
-    } else {
-        unreachable!()
+        _ => Err(Diagnostic::error(
+            Code::NeedNumber,
+            "fail to parse: need number here.",
+            span,
+        )),
     }
 }
 
@@ -212,11 +1311,11 @@ pub fn add_sub_space(stream: &TokenStream) -> Result<String, (String, Position)>
     }
     ret.push_str("  mov rax, ");
 
-    for &token in stream {
+    for token in stream {
         match token {
-            Reserved(operator) if operator == Word::Add => ret.push_str("  add rax, "),
+            Reserved(Word::Add) => ret.push_str("  add rax, "),
             Reserved(_) => ret.push_str("  sub rax, "),
-            Number(number) => ret.push_str(&format!("{}\n", number)),
+            Number(number, _) => ret.push_str(&format!("{}\n", number)),
             Eof => {
                 break;
             }
@@ -283,6 +1382,15 @@ mod tests_parser {
     use std::fs::File;
     use std::io::Write;
 
+    // nodes compare without spans, so any span does for expected trees
+    fn node(kind: NodeKind) -> Node {
+        Node::new(kind, Span::at(0))
+    }
+
+    fn boxed(kind: NodeKind) -> Box<Node> {
+        Box::new(node(kind))
+    }
+
     #[test]
     fn for_expr01() {
         let cases = vec![
@@ -295,55 +1403,645 @@ mod tests_parser {
             "(+4 + 3) / 7 + +1 * (4 - 2)",
         ];
         let answers = vec![
+            Sub(boxed(Add(boxed(Num(5)), boxed(Num(20)))), boxed(Num(4))),
             Sub(
-                Box::new(Add(Box::new(Num(5)), Box::new(Num(20)))),
-                Box::new(Num(4)),
-            ),
-            Sub(
-                Box::new(Add(
-                    Box::new(Sub(Box::new(Num(23)), Box::new(Num(8)))),
-                    Box::new(Num(5)),
+                boxed(Add(
+                    boxed(Sub(boxed(Num(23)), boxed(Num(8)))),
+                    boxed(Num(5)),
                 )),
-                Box::new(Num(3)),
+                boxed(Num(3)),
             ),
+            Add(boxed(Num(1)), boxed(Mul(boxed(Num(2)), boxed(Num(3))))),
+            Num(0),
             Add(
-                Box::new(Num(1)),
-                Box::new(Mul(Box::new(Num(2)), Box::new(Num(3)))),
+                boxed(Div(boxed(Add(boxed(Num(4)), boxed(Num(3)))), boxed(Num(7)))),
+                boxed(Mul(boxed(Num(1)), boxed(Sub(boxed(Num(4)), boxed(Num(2)))))),
             ),
-            Num(0),
+            Add(boxed(Sub(boxed(Num(0)), boxed(Num(10)))), boxed(Num(20))),
             Add(
-                Box::new(Div(
-                    Box::new(Add(Box::new(Num(4)), Box::new(Num(3)))),
-                    Box::new(Num(7)),
-                )),
-                Box::new(Mul(
-                    Box::new(Num(1)),
-                    Box::new(Sub(Box::new(Num(4)), Box::new(Num(2)))),
+                boxed(Div(boxed(Add(boxed(Num(4)), boxed(Num(3)))), boxed(Num(7)))),
+                boxed(Mul(boxed(Num(1)), boxed(Sub(boxed(Num(4)), boxed(Num(2)))))),
+            ),
+        ];
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
+            let mut stream = TokenStream::tokenize(case).unwrap();
+            let ast = expr(&mut stream, &mut Locals::new()).unwrap();
+            assert_eq!(ast, node(answer));
+        }
+    }
+
+    #[test]
+    fn for_operator_precedence() {
+        let cases = vec![
+            "a || b && c",
+            "a | b ^ c & 1",
+            "a == b < c << 1",
+            "1 + a % 3 >> 1",
+            "a = b += c ? 1 : 2",
+            "a ? b : c ? 1 : 2",
+            "a = 1, b = 2",
+            "!a + ~b * -c",
+            "++a - b--",
+            "- -a * +-b",
+            "!~-c",
+        ];
+        let (a, b, c) = (|| boxed(LVar(0)), || boxed(LVar(1)), || boxed(LVar(2)));
+        let answers = vec![
+            LogOr(a(), boxed(LogAnd(b(), c()))),
+            BitOr(a(), boxed(BitXor(b(), boxed(BitAnd(c(), boxed(Num(1))))))),
+            Eq(a(), boxed(Lt(b(), boxed(Shl(c(), boxed(Num(1))))))),
+            Shr(
+                boxed(Add(boxed(Num(1)), boxed(Mod(a(), boxed(Num(3)))))),
+                boxed(Num(1)),
+            ),
+            Assign(
+                a(),
+                boxed(AssignOp(
+                    BinaryOp::Add,
+                    b(),
+                    boxed(Cond(c(), boxed(Num(1)), boxed(Num(2)))),
                 )),
             ),
-            Add(
-                Box::new(Sub(Box::new(Num(0)), Box::new(Num(10)))),
-                Box::new(Num(20)),
+            Cond(a(), b(), boxed(Cond(c(), boxed(Num(1)), boxed(Num(2))))),
+            Comma(
+                boxed(Assign(a(), boxed(Num(1)))),
+                boxed(Assign(b(), boxed(Num(2)))),
             ),
             Add(
-                Box::new(Div(
-                    Box::new(Add(Box::new(Num(4)), Box::new(Num(3)))),
-                    Box::new(Num(7)),
-                )),
-                Box::new(Mul(
-                    Box::new(Num(1)),
-                    Box::new(Sub(Box::new(Num(4)), Box::new(Num(2)))),
-                )),
+                boxed(Not(a())),
+                boxed(Mul(boxed(BitNot(b())), boxed(Sub(boxed(Num(0)), c())))),
+            ),
+            Sub(
+                boxed(AssignOp(BinaryOp::Add, a(), boxed(Num(1)))),
+                boxed(PostDec(b())),
+            ),
+            Mul(
+                boxed(Sub(boxed(Num(0)), boxed(Sub(boxed(Num(0)), a())))),
+                boxed(Sub(boxed(Num(0)), b())),
             ),
+            Not(boxed(BitNot(boxed(Sub(boxed(Num(0)), c()))))),
         ];
-        for (case, answer) in cases
-            .into_iter()
-            .map(|s| s.to_string())
-            .zip(answers.into_iter())
-        {
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
             let mut stream = TokenStream::tokenize(case).unwrap();
-            let ast = expr(&mut stream);
-            assert_eq!(ast, answer);
+            let mut locals = Locals::new();
+            for name in &["a", "b", "c"] {
+                locals.index_of(name, Span::at(0));
+            }
+            let ast = expr(&mut stream, &mut locals).unwrap();
+            assert_eq!(ast, node(answer));
+        }
+    }
+
+    #[test]
+    fn for_span() {
+        let mut stream = TokenStream::tokenize("a = 1 + -b;".to_string()).unwrap();
+        let ast = expr(&mut stream, &mut Locals::new()).unwrap();
+        assert_eq!(ast.span, Span { start: 2, end: 3 });
+        match ast.kind {
+            Assign(left, right) => {
+                assert_eq!(left.span, Span { start: 0, end: 1 });
+                assert_eq!(right.span, Span { start: 6, end: 7 });
+                match right.kind {
+                    Add(_, negative) => assert_eq!(negative.span, Span { start: 8, end: 9 }),
+                    kind => panic!("unexpected {:?}", kind),
+                }
+            }
+            kind => panic!("unexpected {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn for_locals() {
+        let mut stream = TokenStream::tokenize("foo = 1; far = foo; foo;".to_string()).unwrap();
+        let program = parser(&mut stream).unwrap();
+        assert_eq!(
+            program.functions[0].body,
+            vec![
+                node(Assign(boxed(LVar(0)), boxed(Num(1)))),
+                node(Assign(boxed(LVar(1)), boxed(LVar(0)))),
+                node(LVar(0)),
+            ]
+        );
+        let locals = &program.functions[0].locals;
+        assert_eq!(locals.variables[0].offset, 4);
+        assert_eq!(locals.variables[1].offset, 8);
+        assert_eq!(locals.variables[1].span, Span { start: 9, end: 12 });
+        assert_eq!(locals.stack_size(), 16);
+    }
+
+    #[test]
+    fn for_stack_layout() {
+        // each variable is placed below the previous one at its alignment
+        let mut stream = TokenStream::tokenize(
+            "int main() { char c; int a[3]; char s[5]; long l; short *p; int m[2][3]; }"
+                .to_string(),
+        )
+        .unwrap();
+        let program = parser(&mut stream).unwrap();
+        let locals = &program.functions[0].locals;
+        assert_eq!(
+            locals
+                .variables
+                .iter()
+                .map(|variable| variable.offset)
+                .collect::<Vec<_>>(),
+            vec![1, 16, 21, 32, 40, 64]
+        );
+        assert_eq!(
+            locals.variables[5].ty,
+            Type::array_of(Type::array_of(Type::Int, 3), 2)
+        );
+        assert_eq!(locals.stack_size(), 64);
+        // an array parameter is a pointer
+        let mut stream =
+            TokenStream::tokenize("int f(int a[4]) { return 0; }".to_string()).unwrap();
+        let program = parser(&mut stream).unwrap();
+        let locals = &program.functions[0].locals;
+        assert_eq!(locals.variables[0].ty, Type::pointer_to(Type::Int));
+    }
+
+    #[test]
+    fn for_subscript() {
+        let mut stream = TokenStream::tokenize("int a[2][3]; a[1][2];".to_string()).unwrap();
+        let program = parser(&mut stream).unwrap();
+        let row = Deref(boxed(Add(boxed(LVar(0)), boxed(Num(1)))));
+        assert_eq!(
+            program.functions[0].body[1],
+            node(Deref(boxed(Add(boxed(row), boxed(Num(2))))))
+        );
+        let mut stream = TokenStream::tokenize("sizeof(int *[4]); sizeof x;".to_string()).unwrap();
+        let program = parser(&mut stream).unwrap();
+        let body = &program.functions[0].body;
+        assert_eq!(body[0], node(Num(32)));
+        assert_eq!(body[0].ty, Some(Type::UnsignedLong));
+        assert_eq!(body[1], node(Sizeof(boxed(LVar(0)))));
+    }
+
+    #[test]
+    fn for_globals() {
+        // without statements at the top level declarations are file-scope
+        let mut stream = TokenStream::tokenize(
+            "int x, a[2] = {1, {2},}; int main() { int x; return x + a[0]; } long y = 3;"
+                .to_string(),
+        )
+        .unwrap();
+        let program = parser(&mut stream).unwrap();
+        let names: Vec<_> = program.globals.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, vec!["x", "a", "y"]);
+        assert_eq!(program.globals[0].init, None);
+        assert_eq!(program.globals[1].ty, Type::array_of(Type::Int, 2));
+        assert_eq!(
+            program.globals[1].init,
+            Some(Initializer::List(
+                vec![
+                    Initializer::Expr(node(Num(1))),
+                    Initializer::List(
+                        vec![Initializer::Expr(node(Num(2)))],
+                        Span { start: 18, end: 19 }
+                    ),
+                ],
+                Span { start: 14, end: 15 }
+            ))
+        );
+        // the local `x` shadows the global one
+        let row = Deref(boxed(Add(boxed(GVar(1)), boxed(Num(0)))));
+        assert_eq!(
+            program.functions[0].body[1],
+            node(Return(Some(boxed(Add(boxed(LVar(0)), boxed(row))))))
+        );
+        // in a script they are local variables of `main`
+        let mut stream = TokenStream::tokenize("int x = 1; x;".to_string()).unwrap();
+        let program = parser(&mut stream).unwrap();
+        assert!(program.globals.is_empty());
+        assert_eq!(program.functions[0].body[1], node(LVar(0)));
+        // a declaration goes on after its initializer list
+        let mut stream =
+            TokenStream::tokenize("int a[1] = {1}, *p = a; int main() { return *p; }".to_string())
+                .unwrap();
+        let program = parser(&mut stream).unwrap();
+        assert_eq!(program.globals.len(), 2);
+    }
+
+    #[test]
+    fn for_return() {
+        let mut stream = TokenStream::tokenize("a = 2; return a + 1;".to_string()).unwrap();
+        let program = parser(&mut stream).unwrap();
+        assert_eq!(
+            program.functions[0].body,
+            vec![
+                node(Assign(boxed(LVar(0)), boxed(Num(2)))),
+                node(Return(Some(boxed(Add(boxed(LVar(0)), boxed(Num(1))))))),
+            ]
+        );
+    }
+
+    #[test]
+    fn for_control_flow() {
+        let mut stream = TokenStream::tokenize(
+            "if (a) b = 1; else b = 2; while (a < 3) a = a + 1; for (;;) return 0;".to_string(),
+        )
+        .unwrap();
+        let program = parser(&mut stream).unwrap();
+        assert_eq!(
+            program.functions[0].body,
+            vec![
+                node(If(
+                    boxed(LVar(0)),
+                    boxed(Assign(boxed(LVar(1)), boxed(Num(1)))),
+                    Some(boxed(Assign(boxed(LVar(1)), boxed(Num(2))))),
+                )),
+                node(While(
+                    boxed(Lt(boxed(LVar(0)), boxed(Num(3)))),
+                    boxed(Assign(
+                        boxed(LVar(0)),
+                        boxed(Add(boxed(LVar(0)), boxed(Num(1)))),
+                    )),
+                )),
+                node(For(None, None, None, boxed(Return(Some(boxed(Num(0))))))),
+            ]
+        );
+    }
+
+    #[test]
+    fn for_block_scope() {
+        let mut stream = TokenStream::tokenize(
+            "int x = 1; { int x = 2; { int y; y = x; } } { int z; z = x; } w = 3;".to_string(),
+        )
+        .unwrap();
+        let program = parser(&mut stream).unwrap();
+        assert_eq!(
+            program.functions[0].body,
+            vec![
+                node(Block(vec![node(Assign(boxed(LVar(0)), boxed(Num(1))))])),
+                node(Block(vec![
+                    node(Block(vec![node(Assign(boxed(LVar(1)), boxed(Num(2))))])),
+                    node(Block(vec![
+                        node(Block(vec![])),
+                        node(Assign(boxed(LVar(2)), boxed(LVar(1)))),
+                    ])),
+                ])),
+                node(Block(vec![
+                    node(Block(vec![])),
+                    node(Assign(boxed(LVar(3)), boxed(LVar(0)))),
+                ])),
+                node(Assign(boxed(LVar(4)), boxed(Num(3)))),
+            ]
+        );
+        let locals = &program.functions[0].locals;
+        assert_eq!(
+            locals
+                .variables
+                .iter()
+                .map(|variable| variable.offset)
+                .collect::<Vec<_>>(),
+            vec![4, 8, 12, 8, 8]
+        );
+        assert_eq!(locals.stack_size(), 16);
+
+        let mut stream = TokenStream::tokenize("{ a = 1; } { int b; } a;".to_string()).unwrap();
+        let program = parser(&mut stream).unwrap();
+        assert_eq!(
+            program.functions[0].body,
+            vec![
+                node(Block(vec![node(Assign(boxed(LVar(0)), boxed(Num(1))))])),
+                node(Block(vec![node(Block(vec![]))])),
+                node(LVar(0)),
+            ]
+        );
+        assert_eq!(program.functions[0].locals.stack_size(), 16);
+    }
+
+    #[test]
+    fn for_function() {
+        let mut stream = TokenStream::tokenize(
+            "int plus(int x, int y) { int z = x + y; return z; } int main() { return 1; }"
+                .to_string(),
+        )
+        .unwrap();
+        let program = parser(&mut stream).unwrap();
+        assert_eq!(program.functions.len(), 2);
+        let plus = &program.functions[0];
+        assert_eq!(plus.name, "plus");
+        assert_eq!(plus.params, vec![0, 1]);
+        assert_eq!(
+            plus.body,
+            vec![
+                node(Block(vec![node(Assign(
+                    boxed(LVar(2)),
+                    boxed(Add(boxed(LVar(0)), boxed(LVar(1)))),
+                ))])),
+                node(Return(Some(boxed(LVar(2))))),
+            ]
+        );
+        assert_eq!(plus.locals.stack_size(), 16);
+        let main = &program.functions[1];
+        assert_eq!(main.name, "main");
+        assert_eq!(main.params, vec![]);
+        assert_eq!(main.body, vec![node(Return(Some(boxed(Num(1)))))]);
+
+        let mut stream = TokenStream::tokenize("int main() { return 1; } 2;".to_string()).unwrap();
+        let errors = parser(&mut stream).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Code::MainConflict);
+        assert_eq!(errors[0].span, Span { start: 25, end: 26 });
+        assert_eq!(errors[0].labels[0].span, Span { start: 4, end: 8 });
+
+        // `(void)` is an empty parameter list
+        let mut stream = TokenStream::tokenize("int f(void) { return; }".to_string()).unwrap();
+        let program = parser(&mut stream).unwrap();
+        assert_eq!(program.functions[0].params, vec![]);
+        assert_eq!(program.functions[0].body, vec![node(Return(None))]);
+    }
+
+    #[test]
+    fn for_undeclared() {
+        // only statements of a script declare variables on first use
+        let cases = vec![
+            ("int main() { return x; }", 6),
+            ("x = 1; int f() { return x; }", 10),
+            // a global is visible only after its declaration
+            ("int main() { return g; } int g = 3;", 6),
+            // a global whose initializer fails is still declared
+            ("int g = h; int main() { return g; }", 3),
+        ];
+        for (case, index) in cases {
+            assert_eq!(
+                check(case),
+                Err(vec![(Code::Undeclared, index)]),
+                "{}",
+                case
+            );
+        }
+        assert_eq!(check("x = 1; x;"), Ok(()));
+        // a global is visible in its own initializer
+        assert_eq!(check("int *p = &p; int main() { return 0; }"), Ok(()));
+        assert_eq!(check("int g = sizeof(g); int main() { return g; }"), Ok(()));
+    }
+
+    #[test]
+    fn for_call() {
+        let mut stream = TokenStream::tokenize("f(); g(1, a + 2, h(3));".to_string()).unwrap();
+        let program = parser(&mut stream).unwrap();
+        assert_eq!(
+            program.functions[0].body,
+            vec![
+                node(Call {
+                    name: "f".to_string(),
+                    args: vec![]
+                }),
+                node(Call {
+                    name: "g".to_string(),
+                    args: vec![
+                        node(Num(1)),
+                        node(Add(boxed(LVar(0)), boxed(Num(2)))),
+                        node(Call {
+                            name: "h".to_string(),
+                            args: vec![node(Num(3))]
+                        }),
+                    ]
+                }),
+            ]
+        );
+    }
+
+    // codes and token indices of syntax errors
+    fn check(case: &str) -> Result<(), Vec<(Code, usize)>> {
+        let mut stream = TokenStream::tokenize(case.to_string()).unwrap();
+        parser(&mut stream).map(|_| ()).map_err(|errors| {
+            errors
+                .into_iter()
+                .map(|error| {
+                    let index = stream
+                        .tokens
+                        .iter()
+                        .position(|lexeme| lexeme.span.start == error.span.start)
+                        .unwrap();
+                    (error.code, index)
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn for_syntax_error() {
+        // code and token index of the only error of each
+        let cases = vec![
+            "1 +;",
+            "(1 + 2;",
+            "a = 1",
+            "3 = 4;",
+            "{ a;",
+            "int 3;",
+            "if a) b;",
+            "for (;;",
+            "f(1, 2;",
+            "int f(int a int b) { return 0; }",
+            "int f(x) { return 0; }",
+            "int main() { return 0; ",
+            "}",
+            "a ? b;",
+            "1++;",
+            "++(a + b);",
+            "a + 1 -= 2;",
+            "++++a;",
+            "long char x;",
+            "unsigned signed x;",
+            "void x;",
+            "int f(short short a) { return a; }",
+            "&1;",
+            "&(a + b);",
+            "int a[x];",
+            "int a[2;",
+            "void v[2];",
+            "a[1;",
+            "23 - 8+5 3",
+            "(23 - 8+5));",
+            ");",
+            "a[(1];",
+            "a[1]];",
+            "[1];",
+            "a[];",
+            "sizeof(int;",
+            "a = return 3;",
+            "a return 3;",
+            "return return;",
+            "if () a;",
+            "for (;) a;",
+            "if (a)",
+            "a = if (b) c;",
+            "a; }",
+            "{ a }",
+            "int;",
+            "a = int b;",
+            "int f(int) {}",
+            "int f(int x,) {}",
+            "int f() return 1;",
+            "{ int f() {} }",
+            "f(1,);",
+            "f(,1);",
+            "3(4);",
+            ", b;",
+            "f(1 2);",
+            "a ~b;",
+            "a = ,b;",
+        ];
+        let errors = vec![
+            (Code::NeedNumber, 2),
+            (Code::NeedBracket, 4),
+            (Code::NeedSemicolon, 3),
+            (Code::NotAssignable, 1),
+            (Code::UnmatchedBrace, 0),
+            (Code::NeedIdentifier, 1),
+            (Code::NeedBracket, 1),
+            (Code::NeedNumber, 4),
+            (Code::NeedComma, 5),
+            (Code::NeedComma, 5),
+            (Code::NeedType, 3),
+            (Code::UnmatchedBrace, 4),
+            (Code::NeedNumber, 0),
+            (Code::NeedColon, 3),
+            (Code::NotAssignable, 1),
+            (Code::NotAssignable, 0),
+            (Code::NotAssignable, 3),
+            (Code::NotAssignable, 0),
+            (Code::InvalidType, 0),
+            (Code::InvalidType, 0),
+            (Code::InvalidType, 1),
+            (Code::InvalidType, 3),
+            (Code::NotAssignable, 0),
+            (Code::NotAssignable, 0),
+            (Code::NeedNumber, 3),
+            (Code::NeedBracket, 4),
+            (Code::InvalidType, 1),
+            (Code::NeedBracket, 3),
+            (Code::NeedSemicolon, 5),
+            (Code::NeedSemicolon, 7),
+            (Code::NeedNumber, 0),
+            (Code::NeedBracket, 4),
+            (Code::NeedSemicolon, 4),
+            (Code::NeedNumber, 0),
+            (Code::NeedNumber, 2),
+            (Code::NeedBracket, 3),
+            (Code::NeedNumber, 2),
+            (Code::NeedSemicolon, 1),
+            (Code::NeedNumber, 1),
+            (Code::NeedNumber, 2),
+            (Code::NeedNumber, 3),
+            (Code::NeedNumber, 4),
+            (Code::NeedNumber, 2),
+            (Code::NeedNumber, 2),
+            (Code::NeedSemicolon, 2),
+            (Code::NeedIdentifier, 1),
+            (Code::NeedNumber, 2),
+            (Code::NeedIdentifier, 4),
+            (Code::NeedType, 6),
+            (Code::NeedBrace, 4),
+            (Code::NeedSemicolon, 3),
+            (Code::NeedNumber, 4),
+            (Code::NeedNumber, 2),
+            (Code::NeedSemicolon, 1),
+            (Code::NeedNumber, 0),
+            (Code::NeedComma, 3),
+            (Code::NeedSemicolon, 1),
+            (Code::NeedNumber, 2),
+        ];
+        for (case, error) in cases.into_iter().zip(errors) {
+            assert_eq!(check(case), Err(vec![error]), "{}", case);
+        }
+        // whatever the grammar allows is accepted
+        let cases = vec![
+            "+ +9;",
+            "a = -!~b - -c;",
+            "if (a) b; else c;",
+            "for (i = 0; i < 3; i = i + 1) if (i) a; else b;",
+            "{ int x = 1; { int y; y = x; } }",
+            "{}{}",
+            "unsigned long f(char c, short int s) { long long x = c; return x + s; }",
+            "int *f(int *a, char **b) { int *p, **q = &p; *p = *a * 2; return &*a; }",
+            "int f(int a[3], int n) { int m[2][3]; m[1][a[0]] = sizeof(int *[2]) + sizeof m; }",
+            "int a[2][2] = {{1, 2}, {3,},}, *p = &a[1][0]; int main() { return *p; }",
+            "int f(void) { return 0; } void g(void *p) { return; }",
+            "a = f(1, b + 2, g(h(), 3)) * 2;",
+            "a = b % 3 << 1 | c & ~d ^ !e;",
+            "a = b && c || d ? e : f;",
+            "i++; --j; a = b++ + ++c;",
+            "for (i = 0, j = 1; i < 3; i++, j--) a -= i;",
+            "f((a, b), c);",
+        ];
+        for case in cases {
+            assert_eq!(check(case), Ok(()), "{}", case);
+        }
+    }
+
+    #[test]
+    fn for_redefinition() {
+        let cases = vec![
+            ("{ int x; int x; }", 5),
+            ("int f(int a, int a) { return a; }", 7),
+        ];
+        for (case, index) in cases {
+            assert_eq!(
+                check(case),
+                Err(vec![(Code::Redefinition, index)]),
+                "{}",
+                case
+            );
+        }
+        // the previous definition is pointed at
+        let mut stream = TokenStream::tokenize("{ int x; char x; }".to_string()).unwrap();
+        let errors = parser(&mut stream).unwrap_err();
+        assert_eq!(errors[0].labels[0].span, Span { start: 6, end: 7 });
+        // a global is declared once and not as a function
+        let cases = vec![
+            ("int x; int main() { return 0; } long x = 1;", 13),
+            ("int x, *x; int main() { return 0; }", 4),
+            ("int f; int f() { return 0; }", 4),
+            ("int f() { return 0; } char f[2];", 10),
+        ];
+        for (case, index) in cases {
+            assert_eq!(
+                check(case),
+                Err(vec![(Code::Redefinition, index)]),
+                "{}",
+                case
+            );
+        }
+        let mut stream = TokenStream::tokenize("int f; int f() { return 0; }".to_string()).unwrap();
+        let errors = parser(&mut stream).unwrap_err();
+        assert_eq!(errors[0].labels[0].span, Span { start: 4, end: 5 });
+        // a function is defined once, the first definition is kept
+        let case = "int f() { return 1; } long *f() { return 0; } int main() { return f(); }";
+        assert_eq!(check(case), Err(vec![(Code::Redefinition, 11)]));
+        let mut stream = TokenStream::tokenize(case.to_string()).unwrap();
+        let errors = parser(&mut stream).unwrap_err();
+        assert_eq!(errors[0].labels[0].span, Span { start: 4, end: 5 });
+        // a variable of an outer scope can be shadowed
+        for case in ["int x; { int x; }", "for (int i = 0; ; ) { int i; }"] {
+            assert_eq!(check(case), Ok(()), "{}", case);
+        }
+    }
+
+    #[test]
+    fn for_error_recovery() {
+        // each broken statement is reported and parsing goes on after it
+        let case =
+            "int f(int a) { a = ; if (a) { a + ; } return a } int g(x) { x; } int main() { 1 = 2; }";
+        let errors = vec![
+            (Code::NeedNumber, 9),
+            (Code::NeedNumber, 17),
+            (Code::NeedSemicolon, 21),
+            (Code::NeedType, 25),
+            (Code::NotAssignable, 37),
+        ];
+        assert_eq!(check(case), Err(errors));
+        let cases = vec![";3+4;);", "for (;;;) a;", "(a + { b; });"];
+        let errors = vec![
+            vec![(Code::NeedNumber, 0), (Code::NeedNumber, 5)],
+            vec![(Code::NeedNumber, 4), (Code::NeedNumber, 5)],
+            vec![(Code::NeedNumber, 3), (Code::NeedNumber, 7)],
+        ];
+        for (case, errors) in cases.into_iter().zip(errors) {
+            assert_eq!(check(case), Err(errors), "{}", case);
         }
     }
 
@@ -351,11 +2049,7 @@ mod tests_parser {
     fn for_add_sub_space() {
         let cases = vec!["5+20-4", "23 - 8+5- 3"];
         let answers = vec![21, 17];
-        for (case, answer) in cases
-            .into_iter()
-            .map(|s| s.to_string())
-            .zip(answers.into_iter())
-        {
+        for (case, answer) in cases.into_iter().map(|s| s.to_string()).zip(answers) {
             let stream = TokenStream::tokenize(case).unwrap();
             let program = add_sub_space(&stream).unwrap();
             let mut file = File::create("test03.s").unwrap();
@@ -363,7 +2057,7 @@ mod tests_parser {
             file.flush().unwrap();
             let out = Command::new("sh")
                 .arg("-c")
-                .arg(&format!("cc -o test03 test03.s; ./test03; echo $?",))
+                .arg("cc -o test03 test03.s; ./test03; echo $?")
                 .output()
                 .unwrap()
                 .stdout;
@@ -381,14 +2075,14 @@ mod tests_parser {
     fn for_add_sub() {
         let cases = vec!["5+20-4"];
         let answers = vec![21];
-        for (case, answer) in cases.into_iter().zip(answers.into_iter()) {
+        for (case, answer) in cases.into_iter().zip(answers) {
             let program = add_sub(case);
             let mut file = File::create("test02.s").unwrap();
             write!(file, "{}", program).unwrap();
             file.flush().unwrap();
             let out = Command::new("sh")
                 .arg("-c")
-                .arg(&format!("cc -o test02 test02.s; ./test02; echo $?",))
+                .arg("cc -o test02 test02.s; ./test02; echo $?")
                 .output()
                 .unwrap()
                 .stdout;
@@ -406,11 +2100,11 @@ mod tests_parser {
     fn for_return_number() {
         let cases = vec!["0", "42", "255"];
         let answers = vec![0, 42, 255];
-        for (case, answer) in cases.into_iter().zip(answers.into_iter()) {
+        for (case, answer) in cases.into_iter().zip(answers) {
             let program = return_number(case);
             let out = Command::new("sh")
                 .arg("-c")
-                .arg(&format!(
+                .arg(format!(
                     "echo \"{}\" > test01.s; cc -o test01 test01.s; ./test01; echo $?",
                     program
                 ))
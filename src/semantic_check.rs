@@ -0,0 +1,439 @@
+use crate::error::{Code, Diagnostic};
+use crate::parser::{Function, LocalVariable, Node, NodeKind::*, Program};
+use crate::type_check::Type;
+
+// what happened to a local variable so far in evaluation order
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+struct Usage {
+    assigned: bool,
+    read: bool,
+    // named in the operand of sizeof, which uses it without reading it
+    measured: bool,
+}
+
+struct State<'a> {
+    variables: &'a [LocalVariable],
+    usages: Vec<Usage>,
+    warnings: Vec<Diagnostic>,
+}
+
+// warnings for code which compiles but probably does not do what was meant
+pub fn check_program(program: &Program) -> Vec<Diagnostic> {
+    let mut warnings = Vec::new();
+    for function in &program.functions {
+        warnings.append(&mut check_function(function));
+    }
+    warnings.sort_by_key(|warning| warning.span.start);
+    warnings
+}
+
+fn check_function(function: &Function) -> Vec<Diagnostic> {
+    let variables = &function.locals.variables;
+    let mut state = State {
+        variables,
+        usages: vec![Usage::default(); variables.len()],
+        warnings: Vec::new(),
+    };
+    // parameters are declared first and assigned by the caller
+    for usage in state.usages.iter_mut().take(function.params.len()) {
+        usage.assigned = true;
+    }
+    for node in &function.body {
+        check_usage(node, &mut state);
+        check_comparison(node, &mut state.warnings);
+    }
+
+    for (index, usage) in state.usages.iter().enumerate().skip(function.params.len()) {
+        let variable = &variables[index];
+        if usage.read || usage.measured {
+            continue;
+        } else if usage.assigned {
+            state.warnings.push(Diagnostic::warning(
+                Code::UnusedButSet,
+                &format!("variable `{}` is set but never read.", variable.name),
+                variable.span,
+            ));
+        } else {
+            state.warnings.push(Diagnostic::warning(
+                Code::Unused,
+                &format!("variable `{}` is declared but never used.", variable.name),
+                variable.span,
+            ));
+        }
+    }
+    state.warnings
+}
+
+// walk in the order of evaluation so that a read before any assignment is
+// caught, an assignment on any path counts
+fn check_usage(node: &Node, state: &mut State) {
+    match &node.kind {
+        Assign(left, right) => {
+            check_usage(right, state);
+            match &left.kind {
+                LVar(index) => state.usages[*index].assigned = true,
+                _ => check_usage(left, state),
+            }
+        }
+        // the old value is read before it is updated
+        AssignOp(_, left, right) => {
+            check_usage(right, state);
+            check_usage(left, state);
+            if let LVar(index) = &left.kind {
+                state.usages[*index].assigned = true;
+            }
+        }
+        PostInc(operand) | PostDec(operand) => {
+            check_usage(operand, state);
+            if let LVar(index) = &operand.kind {
+                state.usages[*index].assigned = true;
+            }
+        }
+        // the variable may be written and read through the pointer
+        Addr(operand) => match &operand.kind {
+            LVar(index) => {
+                let usage = &mut state.usages[*index];
+                usage.assigned = true;
+                usage.read = true;
+            }
+            _ => check_usage(operand, state),
+        },
+        // an array is never assigned as a whole, it stands for its address
+        // as with `&`
+        LVar(index) if matches!(state.variables[*index].ty, Type::Array(..)) => {
+            let usage = &mut state.usages[*index];
+            usage.assigned = true;
+            usage.read = true;
+        }
+        LVar(index) => {
+            let usage = &mut state.usages[*index];
+            if !usage.assigned && !usage.read {
+                let variable = &state.variables[*index];
+                let mut warning = Diagnostic::warning(
+                    Code::Uninitialized,
+                    &format!(
+                        "variable `{}` is used before being assigned.",
+                        variable.name
+                    ),
+                    node.span,
+                );
+                if variable.span != node.span {
+                    warning = warning.with_label(variable.span, "declared here");
+                }
+                state.warnings.push(warning);
+            }
+            usage.read = true;
+        }
+        Sizeof(operand) => measure(operand, state),
+        For(init, condition, step, body) => {
+            // the step runs after the body
+            for node in init.iter().chain(condition) {
+                check_usage(node, state);
+            }
+            check_usage(body, state);
+            if let Some(step) = step {
+                check_usage(step, state);
+            }
+        }
+        _ => {
+            for child in children(node) {
+                check_usage(child, state);
+            }
+        }
+    }
+}
+
+fn measure(node: &Node, state: &mut State) {
+    match &node.kind {
+        LVar(index) => state.usages[*index].measured = true,
+        Sizeof(operand) => measure(operand, state),
+        _ => {
+            for child in children(node) {
+                measure(child, state);
+            }
+        }
+    }
+}
+
+// warn about the outermost comparison whose result is known at compile time
+fn check_comparison(node: &Node, warnings: &mut Vec<Diagnostic>) {
+    if let Some(value) = compare(node) {
+        let mut warning = Diagnostic::warning(
+            Code::TautologicalCompare,
+            &format!(
+                "comparison is always {}.",
+                if value == 1 { "true" } else { "false" }
+            ),
+            node.span,
+        );
+        if children(node).into_iter().any(is_comparison) {
+            warning = warning.with_help(
+                "comparisons do not chain, `a < b < c` compares the result of `a < b` with `c`",
+            );
+        }
+        warnings.push(warning);
+        return;
+    }
+    for child in children(node) {
+        check_comparison(child, warnings);
+    }
+}
+
+fn is_comparison(node: &Node) -> bool {
+    matches!(node.kind, Eq(_, _) | Ne(_, _) | Le(_, _) | Lt(_, _))
+}
+
+// result of a comparison which does not depend on the value of its operands
+fn compare(node: &Node) -> Option<i64> {
+    let (left, right) = match &node.kind {
+        Eq(left, right) | Ne(left, right) | Le(left, right) | Lt(left, right) => {
+            (range(left)?, range(right)?)
+        }
+        _ => return None,
+    };
+    let ((left_min, left_max), (right_min, right_max)) = (left, right);
+    let equal = if left_min == left_max && left == right {
+        Some(true)
+    } else if left_max < right_min || right_max < left_min {
+        Some(false)
+    } else {
+        None
+    };
+    let result = match &node.kind {
+        Eq(_, _) => equal,
+        Ne(_, _) => equal.map(|equal| !equal),
+        Le(_, _) if left_max <= right_min => Some(true),
+        Le(_, _) if left_min > right_max => Some(false),
+        Lt(_, _) if left_max < right_min => Some(true),
+        Lt(_, _) if left_min >= right_max => Some(false),
+        _ => None,
+    };
+    result.map(i64::from)
+}
+
+// smallest and largest value of an expression when they are known
+fn range(node: &Node) -> Option<(i64, i64)> {
+    let constant = |left: &Node, right: &Node, operation: fn(i64, i64) -> Option<i64>| match (
+        range(left)?,
+        range(right)?,
+    ) {
+        ((left, left_max), (right, right_max)) if left == left_max && right == right_max => {
+            operation(left, right).map(|value| (value, value))
+        }
+        _ => None,
+    };
+    let range = match &node.kind {
+        Num(number) => Some((*number, *number)),
+        Eq(_, _) | Ne(_, _) | Le(_, _) | Lt(_, _) => {
+            Some(compare(node).map_or((0, 1), |value| (value, value)))
+        }
+        LogAnd(_, _) | LogOr(_, _) | Not(_) => Some((0, 1)),
+        Add(left, right) => constant(left, right, i64::checked_add),
+        Sub(left, right) => constant(left, right, i64::checked_sub),
+        Mul(left, right) => constant(left, right, i64::checked_mul),
+        Div(left, right) => constant(left, right, i64::checked_div),
+        Mod(left, right) => constant(left, right, i64::checked_rem),
+        Cast(value) => range(value),
+        _ => None,
+    };
+    // a value which doesn't fit in its type wraps around, so nothing is known
+    match (range, &node.ty) {
+        (Some((min, max)), Some(ty)) if !ty.contains(min) || !ty.contains(max) => None,
+        _ => range,
+    }
+}
+
+fn children(node: &Node) -> Vec<&Node> {
+    match &node.kind {
+        Add(left, right)
+        | Sub(left, right)
+        | Mul(left, right)
+        | Div(left, right)
+        | Mod(left, right)
+        | Shl(left, right)
+        | Shr(left, right)
+        | BitAnd(left, right)
+        | BitOr(left, right)
+        | BitXor(left, right)
+        | Eq(left, right)
+        | Ne(left, right)
+        | Le(left, right)
+        | Lt(left, right)
+        | LogAnd(left, right)
+        | LogOr(left, right)
+        | Comma(left, right)
+        | Assign(left, right)
+        | AssignOp(_, left, right)
+        | While(left, right) => vec![left, right],
+        Return(value) => value.iter().map(|value| value.as_ref()).collect(),
+        Not(value) | BitNot(value) | PostInc(value) | PostDec(value) | Cast(value)
+        | Addr(value) | Deref(value) => vec![value],
+        Cond(condition, then, otherwise) => vec![condition, then, otherwise],
+        If(condition, then, otherwise) => {
+            let mut children = vec![condition.as_ref(), then.as_ref()];
+            children.extend(otherwise.as_deref());
+            children
+        }
+        For(init, condition, step, body) => init
+            .iter()
+            .chain(condition)
+            .chain(step)
+            .map(|node| node.as_ref())
+            .chain(Some(body.as_ref()))
+            .collect(),
+        Block(nodes) => nodes.iter().collect(),
+        Call { args, .. } => args.iter().collect(),
+        // the operand of sizeof is not evaluated
+        LVar(_) | GVar(_) | Str(_) | Num(_) | Sizeof(_) => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests_semantic_check {
+    use super::*;
+    use crate::lexer::TokenStream;
+    use crate::parser::parser;
+    use crate::type_check::check_types;
+
+    // codes and bytes of the warnings for a program as the driver checks it
+    fn check(case: &str) -> Vec<(Code, usize)> {
+        let mut stream = TokenStream::tokenize(case.to_string()).unwrap();
+        let mut program = parser(&mut stream).unwrap();
+        check_types(&mut program).unwrap();
+        check_program(&program)
+            .into_iter()
+            .map(|warning| (warning.code, warning.span.start))
+            .collect()
+    }
+
+    #[test]
+    fn for_uninitialized() {
+        let cases = vec![
+            "a + 1;",
+            "int main() { int x; return x; }",
+            "int main() { int x = x; return x; }",
+            "int main() { int i; while (i < 3) i = i + 1; return i; }",
+            "int main() { int x; if (1) x = 1; return x; }",
+            "int f(int a) { return a; }",
+            "int main() { int s = 0; for (int i = 0; i < 3; i = i + 1) s = s + i; return s; }",
+            "int main() { int x; x += 1; return x; }",
+            "int main() { int i; i++; return 0 && i; }",
+            "int main() { int x; int *p = &x; *p = 1; return x; }",
+            "int main() { int *p; return *p; }",
+            "int main() { int a[2]; a[0] = 1; return a[0]; }",
+            "int main() { int a[2]; int *p = a; return *p + a[1]; }",
+        ];
+        let answers = vec![
+            vec![(Code::Uninitialized, 0)],
+            vec![(Code::Uninitialized, 27)],
+            vec![(Code::Uninitialized, 21)],
+            vec![(Code::Uninitialized, 27)],
+            vec![],
+            vec![],
+            vec![],
+            vec![(Code::Uninitialized, 20)],
+            vec![(Code::Uninitialized, 20)],
+            vec![],
+            vec![(Code::Uninitialized, 29)],
+            vec![],
+            vec![],
+        ];
+        for (case, answer) in cases.into_iter().zip(answers) {
+            assert_eq!(check(case), answer, "{}", case);
+        }
+    }
+
+    #[test]
+    fn for_unused_but_set() {
+        let cases = vec![
+            "int main() { int x = 1; return 0; }",
+            "a = 1; b = a;",
+            "int f(int a) { a = 1; return 0; }",
+            "int main() { int x; x = 1; x = 2; return x; }",
+        ];
+        let answers = vec![
+            vec![(Code::UnusedButSet, 17)],
+            vec![(Code::UnusedButSet, 7)],
+            vec![],
+            vec![],
+        ];
+        for (case, answer) in cases.into_iter().zip(answers) {
+            assert_eq!(check(case), answer, "{}", case);
+        }
+    }
+
+    #[test]
+    fn for_unused() {
+        let cases = vec![
+            "int main() { int x; return 0; }",
+            "int main() { int x; int y; return sizeof y; }",
+            "int main() { int a[2]; return 0; }",
+            "int f(int a) { return 0; }",
+        ];
+        let answers = vec![
+            vec![(Code::Unused, 17)],
+            vec![(Code::Unused, 17)],
+            vec![(Code::Unused, 17)],
+            vec![],
+        ];
+        for (case, answer) in cases.into_iter().zip(answers) {
+            assert_eq!(check(case), answer, "{}", case);
+        }
+    }
+
+    #[test]
+    fn for_sizeof() {
+        // the operand is a use but not a read, and the size is not known to
+        // the comparison
+        let cases = vec![
+            "int main() { int x = 4; return sizeof x == 4; }",
+            "int main() { int x; return sizeof(x + 1); }",
+            "int main() { int x; x = sizeof x; return x; }",
+            "int main() { int x; sizeof x; return x; }",
+        ];
+        let answers = vec![vec![], vec![], vec![], vec![(Code::Uninitialized, 37)]];
+        for (case, answer) in cases.into_iter().zip(answers) {
+            assert_eq!(check(case), answer, "{}", case);
+        }
+    }
+
+    #[test]
+    fn for_tautological_compare() {
+        let cases = vec![
+            "return 0 < 0 < 7;",
+            "a = 1; return a < 3 < 7;",
+            "return 1 + 1 == 2;",
+            "return 3 >= 4;",
+            "a = 1; return a < 3 < 1;",
+            "a = 1; return a == a;",
+            "return 1 / 0 == 0;",
+        ];
+        let answers = vec![
+            vec![(Code::TautologicalCompare, 13)],
+            vec![(Code::TautologicalCompare, 20)],
+            vec![(Code::TautologicalCompare, 13)],
+            vec![(Code::TautologicalCompare, 9)],
+            vec![],
+            vec![],
+            vec![],
+        ];
+        for (case, answer) in cases.into_iter().zip(answers) {
+            assert_eq!(check(case), answer, "{}", case);
+        }
+    }
+
+    #[test]
+    fn for_message() {
+        let mut stream =
+            TokenStream::tokenize("int main() { int x; return x + (0 < 0 < 7); }".to_string())
+                .unwrap();
+        let warnings = check_program(&parser(&mut stream).unwrap());
+        assert_eq!(
+            warnings[0].message,
+            "variable `x` is used before being assigned."
+        );
+        assert_eq!(warnings[0].labels[0].span.start, 17);
+        assert_eq!(warnings[1].message, "comparison is always true.");
+        assert!(warnings[1].help.is_some());
+    }
+}
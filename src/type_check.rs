@@ -0,0 +1,1172 @@
+use crate::error::{Code, Diagnostic, Span};
+use crate::lexer::IntegerType;
+use crate::parser::{BinaryOp, Datum, Initializer, LocalVariable, Node, NodeKind::*, Program};
+use std::collections::HashMap;
+use std::mem;
+
+// char is signed as on x86-64, values are kept sign or zero extended to 64 bits
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Type {
+    Void,
+    Char,
+    Short,
+    Int,
+    Long,
+    UnsignedChar,
+    UnsignedShort,
+    UnsignedInt,
+    UnsignedLong,
+    Pointer(Box<Type>),
+    // element type and length
+    Array(Box<Type>, usize),
+}
+
+impl Type {
+    pub fn pointer_to(base: Type) -> Self {
+        Type::Pointer(Box::new(base))
+    }
+
+    pub fn array_of(base: Type, length: usize) -> Self {
+        Type::Array(Box::new(base), length)
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Type::Void | Type::Char | Type::UnsignedChar => 1,
+            Type::Short | Type::UnsignedShort => 2,
+            Type::Int | Type::UnsignedInt => 4,
+            Type::Long | Type::UnsignedLong | Type::Pointer(_) => 8,
+            Type::Array(base, length) => base.size() * length,
+        }
+    }
+
+    // an array is aligned as its elements
+    pub fn align(&self) -> usize {
+        match self {
+            Type::Array(base, _) => base.align(),
+            _ => self.size(),
+        }
+    }
+
+    // addresses compare as unsigned numbers
+    pub fn is_unsigned(&self) -> bool {
+        matches!(
+            self,
+            Type::UnsignedChar
+                | Type::UnsignedShort
+                | Type::UnsignedInt
+                | Type::UnsignedLong
+                | Type::Pointer(_)
+        )
+    }
+
+    pub fn is_integer(&self) -> bool {
+        !matches!(self, Type::Void | Type::Pointer(_) | Type::Array(..))
+    }
+
+    // type pointed to by a pointer, or the elements of an array
+    pub fn base(&self) -> Option<&Type> {
+        match self {
+            Type::Pointer(base) | Type::Array(base, _) => Some(base),
+            _ => None,
+        }
+    }
+
+    // an array used as a value is a pointer to its first element
+    pub fn decay(&self) -> Self {
+        match self {
+            Type::Array(base, _) => Type::Pointer(base.clone()),
+            _ => self.clone(),
+        }
+    }
+
+    pub fn to_unsigned(&self) -> Self {
+        match self {
+            Type::Char => Type::UnsignedChar,
+            Type::Short => Type::UnsignedShort,
+            Type::Int => Type::UnsignedInt,
+            Type::Long => Type::UnsignedLong,
+            _ => self.clone(),
+        }
+    }
+
+    // integer promotion, types narrower than int become int
+    pub fn promote(&self) -> Self {
+        if self.is_integer() && self.size() < 4 {
+            Type::Int
+        } else {
+            self.decay()
+        }
+    }
+
+    // usual arithmetic conversions of the operands of a binary operator, an
+    // integer meets a pointer only where it is taken as an address
+    pub fn common(&self, other: &Self) -> Self {
+        if self.base().is_some() {
+            return self.decay();
+        } else if other.base().is_some() {
+            return other.decay();
+        }
+        let (left, right) = (self.promote(), other.promote());
+        if left == right {
+            return left;
+        }
+        let (unsigned, signed) = match (left.is_unsigned(), right.is_unsigned()) {
+            (true, false) => (left, right),
+            (false, true) => (right, left),
+            // the same signedness, the wider one wins
+            _ => {
+                return if left.size() >= right.size() {
+                    left
+                } else {
+                    right
+                }
+            }
+        };
+        if unsigned.size() >= signed.size() {
+            unsigned
+        } else {
+            // `long` holds every value of `unsigned int`
+            signed
+        }
+    }
+
+    // whether a value survives the conversion to this type
+    pub fn contains(&self, value: i64) -> bool {
+        let bits = 8 * self.size() as u32;
+        match (self, self.is_unsigned()) {
+            (Type::Void, _) | (Type::Array(..), _) => false,
+            (_, true) if bits == 64 => value >= 0,
+            (_, true) => 0 <= value && value < 1 << bits,
+            (_, false) => {
+                let bound = 1i128 << (bits - 1);
+                -bound <= value as i128 && (value as i128) < bound
+            }
+        }
+    }
+
+    // a value converted to this type, extended to 64 bits as the generator
+    // keeps it
+    pub fn wrap(&self, value: i64) -> i64 {
+        match (self.size(), self.is_unsigned()) {
+            (1, true) => value as u8 as i64,
+            (1, false) => value as i8 as i64,
+            (2, true) => value as u16 as i64,
+            (2, false) => value as i16 as i64,
+            (4, true) => value as u32 as i64,
+            (4, false) => value as i32 as i64,
+            _ => value,
+        }
+    }
+}
+
+impl From<IntegerType> for Type {
+    // long long has the same width as long
+    fn from(ty: IntegerType) -> Self {
+        match ty {
+            IntegerType::Int => Type::Int,
+            IntegerType::UnsignedInt => Type::UnsignedInt,
+            IntegerType::Long | IntegerType::LongLong => Type::Long,
+            IntegerType::UnsignedLong | IntegerType::UnsignedLongLong => Type::UnsignedLong,
+        }
+    }
+}
+
+// type in which a compound assignment or binary operator computes
+pub fn operation_type(operator: BinaryOp, left: &Type, right: &Type) -> Type {
+    match operator {
+        BinaryOp::Shl | BinaryOp::Shr => left.promote(),
+        _ => left.common(right),
+    }
+}
+
+// return and parameter types of a function
+type Signature = (Type, Vec<Type>);
+
+struct Checker<'a> {
+    variables: &'a [LocalVariable],
+    // names and types of the file-scope variables
+    globals: &'a [(String, Type)],
+    // string literals met so far, each kept once
+    strings: &'a mut Vec<Vec<u8>>,
+    signatures: &'a HashMap<String, Signature>,
+    return_type: Type,
+    errors: Vec<Diagnostic>,
+}
+
+// attach a type to every expression and make implicit conversions explicit
+// as `Cast` nodes, so that the generator needs no rules of its own
+pub fn check_types(program: &mut Program) -> Result<(), Vec<Diagnostic>> {
+    let signatures: HashMap<String, Signature> = program
+        .functions
+        .iter()
+        .map(|function| {
+            let params = function
+                .params
+                .iter()
+                .map(|&index| function.locals.variables[index].ty.clone())
+                .collect();
+            (
+                function.name.clone(),
+                (function.return_type.clone(), params),
+            )
+        })
+        .collect();
+    let globals: Vec<(String, Type)> = program
+        .globals
+        .iter()
+        .map(|global| (global.name.clone(), global.ty.clone()))
+        .collect();
+    let mut errors = Vec::new();
+    let mut strings = Vec::new();
+    for global in program.globals.iter_mut() {
+        if let Some(init) = &mut global.init {
+            let mut checker = Checker {
+                variables: &[],
+                globals: &globals,
+                strings: &mut strings,
+                signatures: &signatures,
+                return_type: Type::Void,
+                errors: Vec::new(),
+            };
+            initialize(&mut checker, &global.ty, init, &mut global.data);
+            errors.append(&mut checker.errors);
+        }
+    }
+    for function in program.functions.iter_mut() {
+        let mut checker = Checker {
+            variables: &function.locals.variables,
+            globals: &globals,
+            strings: &mut strings,
+            signatures: &signatures,
+            return_type: function.return_type.clone(),
+            errors: Vec::new(),
+        };
+        for node in function.body.iter_mut() {
+            statement(&mut checker, node);
+        }
+        errors.append(&mut checker.errors);
+    }
+    program.strings = strings;
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn statement(checker: &mut Checker, node: &mut Node) {
+    let span = node.span;
+    match &mut node.kind {
+        Return(value) => {
+            let return_type = checker.return_type.clone();
+            match value {
+                Some(value) if return_type == Type::Void => {
+                    expression(checker, value);
+                    checker.errors.push(Diagnostic::error(
+                        Code::ReturnValue,
+                        "fail to compile: void function should not return a value.",
+                        value.span,
+                    ));
+                }
+                Some(value) => {
+                    expression(checker, value);
+                    coerce(checker, value, return_type);
+                }
+                None if return_type != Type::Void => {
+                    checker.errors.push(Diagnostic::error(
+                        Code::ReturnValue,
+                        "fail to compile: non-void function should return a value.",
+                        span,
+                    ));
+                }
+                None => {}
+            }
+        }
+        If(condition, then, otherwise) => {
+            condition_of(checker, condition);
+            statement(checker, then);
+            if let Some(otherwise) = otherwise {
+                statement(checker, otherwise);
+            }
+        }
+        While(condition, body) => {
+            condition_of(checker, condition);
+            statement(checker, body);
+        }
+        For(init, condition, step, body) => {
+            if let Some(init) = init {
+                statement(checker, init);
+            }
+            if let Some(condition) = condition {
+                condition_of(checker, condition);
+            }
+            if let Some(step) = step {
+                expression(checker, step);
+            }
+            statement(checker, body);
+        }
+        Block(nodes) => {
+            for node in nodes.iter_mut() {
+                statement(checker, node);
+            }
+        }
+        // the value of an expression statement is discarded, void is fine
+        _ => expression(checker, node),
+    }
+}
+
+fn expression(checker: &mut Checker, node: &mut Node) {
+    // `p - q` counts elements, the byte distance is divided by their size
+    let mut distance = None;
+    let subtract = matches!(node.kind, Sub(..));
+    // the operand is not evaluated, only its type matters
+    if let Sizeof(operand) = &mut node.kind {
+        expression(checker, operand);
+        node.ty = Some(Type::UnsignedLong);
+        return;
+    }
+    let ty = match &mut node.kind {
+        Num(_) => node.ty.clone().unwrap_or(Type::Int),
+        LVar(index) => checker.variables[*index].ty.clone(),
+        GVar(index) => checker.globals[*index].1.clone(),
+        // an array of char with room for the null
+        Str(bytes) => {
+            if !checker.strings.contains(bytes) {
+                checker.strings.push(bytes.clone());
+            }
+            Type::array_of(Type::Char, bytes.len() + 1)
+        }
+        Add(left, right) | Sub(left, right) => {
+            expression(checker, left);
+            expression(checker, right);
+            match (left.ty().base().cloned(), right.ty().base().cloned()) {
+                (None, None) => arithmetic(checker, left, right),
+                (Some(base), None) => {
+                    decay(checker, left);
+                    scale(checker, right, &base);
+                    left.ty().clone()
+                }
+                // `n + p` is `p + n`
+                (None, Some(base)) if !subtract => {
+                    mem::swap(left, right);
+                    decay(checker, left);
+                    scale(checker, right, &base);
+                    left.ty().clone()
+                }
+                (Some(base), Some(other)) if subtract && base == other => {
+                    decay(checker, left);
+                    decay(checker, right);
+                    distance = Some(base.size());
+                    Type::Long
+                }
+                _ => {
+                    invalid_operand(checker, node.span);
+                    Type::Int
+                }
+            }
+        }
+        Mul(left, right)
+        | Div(left, right)
+        | Mod(left, right)
+        | BitAnd(left, right)
+        | BitOr(left, right)
+        | BitXor(left, right) => {
+            expression(checker, left);
+            expression(checker, right);
+            arithmetic(checker, left, right)
+        }
+        // the operands of a shift are promoted separately
+        Shl(left, right) | Shr(left, right) => {
+            expression(checker, left);
+            expression(checker, right);
+            integer_operand(checker, left);
+            integer_operand(checker, right);
+            let (ty, count) = (left.ty().promote(), right.ty().promote());
+            convert(checker, left, ty.clone());
+            convert(checker, right, count);
+            ty
+        }
+        Eq(left, right) | Ne(left, right) | Le(left, right) | Lt(left, right) => {
+            expression(checker, left);
+            expression(checker, right);
+            let ty = left.ty().common(right.ty());
+            convert(checker, left, ty.clone());
+            convert(checker, right, ty);
+            Type::Int
+        }
+        LogAnd(left, right) | LogOr(left, right) => {
+            condition_of(checker, left);
+            condition_of(checker, right);
+            Type::Int
+        }
+        Not(operand) => {
+            condition_of(checker, operand);
+            Type::Int
+        }
+        BitNot(operand) => {
+            expression(checker, operand);
+            integer_operand(checker, operand);
+            let ty = operand.ty().promote();
+            convert(checker, operand, ty.clone());
+            ty
+        }
+        Cond(condition, then, otherwise) => {
+            condition_of(checker, condition);
+            expression(checker, then);
+            expression(checker, otherwise);
+            if then.ty() == &Type::Void && otherwise.ty() == &Type::Void {
+                Type::Void
+            } else {
+                let ty = then.ty().common(otherwise.ty());
+                convert(checker, then, ty.clone());
+                convert(checker, otherwise, ty.clone());
+                ty
+            }
+        }
+        Comma(left, right) => {
+            expression(checker, left);
+            expression(checker, right);
+            decay(checker, right);
+            right.ty().clone()
+        }
+        Assign(left, right) => {
+            expression(checker, left);
+            expression(checker, right);
+            assignable(checker, left);
+            let ty = left.ty().clone();
+            coerce(checker, right, ty.clone());
+            ty
+        }
+        AssignOp(operator, left, right) => {
+            expression(checker, left);
+            expression(checker, right);
+            assignable(checker, left);
+            match (operator, left.ty().base().cloned()) {
+                (BinaryOp::Add, Some(base)) | (BinaryOp::Sub, Some(base)) => {
+                    scale(checker, right, &base)
+                }
+                (_, Some(_)) => invalid_operand(checker, node.span),
+                (BinaryOp::Shl, None) | (BinaryOp::Shr, None) => {
+                    integer_operand(checker, right);
+                    let ty = right.ty().promote();
+                    convert(checker, right, ty);
+                }
+                (_, None) => {
+                    integer_operand(checker, right);
+                    let ty = left.ty().common(right.ty());
+                    convert(checker, right, ty);
+                }
+            }
+            left.ty().clone()
+        }
+        PostInc(operand) | PostDec(operand) => {
+            expression(checker, operand);
+            assignable(checker, operand);
+            operand.ty().clone()
+        }
+        Addr(operand) => {
+            expression(checker, operand);
+            Type::pointer_to(operand.ty().clone())
+        }
+        Deref(operand) => {
+            expression(checker, operand);
+            match operand.ty().base() {
+                Some(Type::Void) | None => {
+                    checker.errors.push(Diagnostic::error(
+                        Code::InvalidOperand,
+                        "fail to compile: only a pointer to an object can be dereferenced.",
+                        node.span,
+                    ));
+                    Type::Int
+                }
+                Some(base) => base.clone(),
+            }
+        }
+        Call { name, args } => {
+            // an unknown function is assumed to be `int name()`
+            let signatures = checker.signatures;
+            let (return_type, params) = match signatures.get(name.as_str()) {
+                Some((return_type, params)) => {
+                    if params.len() != args.len() {
+                        checker.errors.push(Diagnostic::error(
+                            Code::ArgumentCount,
+                            &format!(
+                                "fail to compile: `{}` takes {} argument{} but {} {} given.",
+                                name,
+                                params.len(),
+                                if params.len() == 1 { "" } else { "s" },
+                                args.len(),
+                                if args.len() == 1 { "was" } else { "were" }
+                            ),
+                            node.span,
+                        ));
+                    }
+                    (return_type.clone(), params.as_slice())
+                }
+                None => (Type::Int, &[][..]),
+            };
+            for (index, arg) in args.iter_mut().enumerate() {
+                expression(checker, arg);
+                match params.get(index) {
+                    Some(ty) => {
+                        coerce(checker, arg, ty.clone());
+                    }
+                    None => {
+                        let ty = arg.ty().promote();
+                        convert(checker, arg, ty);
+                    }
+                }
+            }
+            return_type
+        }
+        Cast(_) => return,
+        Sizeof(_) => unreachable!("inner error: sizeof is handled above"),
+        Return(_) | If(..) | While(..) | For(..) | Block(_) => {
+            unreachable!("inner error: statement in expression")
+        }
+    };
+    node.ty = Some(ty);
+    if let Some(size) = distance {
+        let span = node.span;
+        let difference = mem::replace(node, Node::new(Num(0), span));
+        *node = Node::new(Div(Box::new(difference), Box::new(long(size, span))), span);
+        node.ty = Some(Type::Long);
+    }
+}
+
+// lay out the contents of a file-scope variable, missing elements of an
+// array are zero
+fn initialize(checker: &mut Checker, ty: &Type, init: &mut Initializer, data: &mut Vec<Datum>) {
+    match (ty, init) {
+        (Type::Array(base, length), Initializer::List(items, span)) => {
+            if items.len() > *length {
+                not_constant(checker, "excess elements in array initializer", *span);
+            }
+            for item in items.iter_mut().take(*length) {
+                initialize(checker, base, item, data);
+            }
+            let rest = length.saturating_sub(items.len());
+            if rest > 0 {
+                data.push(Datum::Zero(rest * base.size()));
+            }
+        }
+        (Type::Array(..), Initializer::Expr(node)) => {
+            not_constant(checker, "array needs an initializer list", node.span);
+        }
+        // `int x = {1};` and `int x = {};`
+        (_, Initializer::List(items, span)) => match items.as_mut_slice() {
+            [] => data.push(Datum::Zero(ty.size())),
+            [item] => initialize(checker, ty, item, data),
+            _ => not_constant(checker, "excess elements in scalar initializer", *span),
+        },
+        (_, Initializer::Expr(node)) => {
+            expression(checker, node);
+            if let Type::Array(..) = node.ty() {
+                decay(checker, node);
+            }
+            // a value of the wrong type is not reported as not constant
+            if !coerce(checker, node, ty.clone()) {
+                return;
+            }
+            match evaluate(node, checker.strings) {
+                Some((None, value)) => data.push(Datum::Integer(ty.size(), value)),
+                Some((Some(Base::Global(index)), offset)) => {
+                    let name = checker.globals[index].0.clone();
+                    data.push(Datum::Address(name, offset));
+                }
+                Some((Some(Base::Literal(index)), offset)) => {
+                    data.push(Datum::Literal(index, offset));
+                }
+                None => not_constant(checker, "initializer element is not constant", node.span),
+            }
+        }
+    }
+}
+
+fn not_constant(checker: &mut Checker, message: &str, span: Span) {
+    checker.errors.push(Diagnostic::error(
+        Code::NotConstant,
+        &format!("fail to compile: {}.", message),
+        span,
+    ));
+}
+
+// what the address in a constant expression is relative to
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Base {
+    // index into the globals
+    Global(usize),
+    // index into the string literals
+    Literal(usize),
+}
+
+// the value of a constant expression, which may be an offset from an address
+fn evaluate(node: &Node, strings: &[Vec<u8>]) -> Option<(Option<Base>, i64)> {
+    let ty = node.ty();
+    let evaluate = |node: &Node| evaluate(node, strings);
+    let integer = |left: &Node, right: &Node| match (evaluate(left)?, evaluate(right)?) {
+        ((None, x), (None, y)) => Some((x, y)),
+        _ => None,
+    };
+    let value = match &node.kind {
+        Num(value) => *value,
+        Sizeof(operand) => operand.ty().size() as i64,
+        // an array stands for the address of its first element
+        GVar(index) if matches!(ty, Type::Array(..)) => {
+            return Some((Some(Base::Global(*index)), 0))
+        }
+        Str(bytes) => {
+            let index = strings.iter().position(|string| string == bytes)?;
+            return Some((Some(Base::Literal(index)), 0));
+        }
+        Addr(operand) => {
+            return match &operand.kind {
+                GVar(index) => Some((Some(Base::Global(*index)), 0)),
+                Deref(pointer) => evaluate(pointer),
+                _ => None,
+            }
+        }
+        Cast(operand) => {
+            let (label, value) = evaluate(operand)?;
+            if label.is_some() && ty.size() < 8 {
+                return None;
+            }
+            return Some((label, ty.wrap(value)));
+        }
+        Add(left, right) => {
+            let ((a, x), (b, y)) = (evaluate(left)?, evaluate(right)?);
+            if a.is_some() && b.is_some() {
+                return None;
+            }
+            return Some((a.or(b), ty.wrap(x.wrapping_add(y))));
+        }
+        Sub(left, right) => {
+            return match (evaluate(left)?, evaluate(right)?) {
+                ((a, x), (None, y)) => Some((a, ty.wrap(x.wrapping_sub(y)))),
+                // the distance between addresses in the same global
+                ((Some(a), x), (Some(b), y)) if a == b => Some((None, x.wrapping_sub(y))),
+                _ => None,
+            };
+        }
+        Mul(left, right) => {
+            let (x, y) = integer(left, right)?;
+            x.wrapping_mul(y)
+        }
+        Div(left, right) | Mod(left, right) => {
+            let (x, y) = integer(left, right)?;
+            if y == 0 {
+                return None;
+            }
+            match (&node.kind, ty.is_unsigned()) {
+                (Div(..), true) => (x as u64 / y as u64) as i64,
+                (Div(..), false) => x.wrapping_div(y),
+                (_, true) => (x as u64 % y as u64) as i64,
+                (_, false) => x.wrapping_rem(y),
+            }
+        }
+        Shl(left, right) => {
+            let (x, y) = integer(left, right)?;
+            x.wrapping_shl(y as u32)
+        }
+        Shr(left, right) => {
+            let (x, y) = integer(left, right)?;
+            if ty.is_unsigned() {
+                (x as u64).wrapping_shr(y as u32) as i64
+            } else {
+                x.wrapping_shr(y as u32)
+            }
+        }
+        BitAnd(left, right) => {
+            let (x, y) = integer(left, right)?;
+            x & y
+        }
+        BitOr(left, right) => {
+            let (x, y) = integer(left, right)?;
+            x | y
+        }
+        BitXor(left, right) => {
+            let (x, y) = integer(left, right)?;
+            x ^ y
+        }
+        Eq(left, right) => {
+            let (x, y) = integer(left, right)?;
+            (x == y) as i64
+        }
+        Ne(left, right) => {
+            let (x, y) = integer(left, right)?;
+            (x != y) as i64
+        }
+        Le(left, right) | Lt(left, right) => {
+            let (x, y) = integer(left, right)?;
+            let ordering = if left.ty().is_unsigned() {
+                (x as u64).cmp(&(y as u64))
+            } else {
+                x.cmp(&y)
+            };
+            match node.kind {
+                Le(..) => ordering.is_le() as i64,
+                _ => ordering.is_lt() as i64,
+            }
+        }
+        LogAnd(left, right) => {
+            let (x, y) = integer(left, right)?;
+            (x != 0 && y != 0) as i64
+        }
+        LogOr(left, right) => {
+            let (x, y) = integer(left, right)?;
+            (x != 0 || y != 0) as i64
+        }
+        Not(operand) => match evaluate(operand)? {
+            (None, x) => (x == 0) as i64,
+            _ => return None,
+        },
+        BitNot(operand) => match evaluate(operand)? {
+            (None, x) => !x,
+            _ => return None,
+        },
+        Cond(condition, then, otherwise) => match evaluate(condition)? {
+            (None, 0) => return evaluate(otherwise),
+            (None, _) => return evaluate(then),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some((None, ty.wrap(value)))
+}
+
+// integer operands converted to their common type
+fn arithmetic(checker: &mut Checker, left: &mut Node, right: &mut Node) -> Type {
+    integer_operand(checker, left);
+    integer_operand(checker, right);
+    let ty = left.ty().common(right.ty());
+    convert(checker, left, ty.clone());
+    convert(checker, right, ty.clone());
+    ty
+}
+
+// use an array as the pointer to its first element
+fn decay(checker: &mut Checker, node: &mut Node) {
+    let ty = node.ty().decay();
+    convert(checker, node, ty);
+}
+
+// an array as a whole can't be assigned
+fn assignable(checker: &mut Checker, node: &Node) {
+    if let Type::Array(..) = node.ty() {
+        checker.errors.push(Diagnostic::error(
+            Code::NotAssignable,
+            "fail to compile: array is not assignable.",
+            node.span,
+        ));
+    }
+}
+
+// turn the integer added to a pointer into a distance in bytes
+fn scale(checker: &mut Checker, node: &mut Node, base: &Type) {
+    integer_operand(checker, node);
+    convert(checker, node, Type::Long);
+    let span = node.span;
+    let count = mem::replace(node, Node::new(Num(0), span));
+    *node = Node::new(
+        Mul(Box::new(count), Box::new(long(base.size(), span))),
+        span,
+    );
+    node.ty = Some(Type::Long);
+}
+
+fn long(value: usize, span: Span) -> Node {
+    let mut node = Node::new(Num(value as i64), span);
+    node.ty = Some(Type::Long);
+    node
+}
+
+// void is reported when it is converted
+fn integer_operand(checker: &mut Checker, node: &Node) {
+    if let Type::Pointer(_) = node.ty() {
+        invalid_operand(checker, node.span);
+    }
+}
+
+fn invalid_operand(checker: &mut Checker, span: Span) {
+    checker.errors.push(Diagnostic::error(
+        Code::InvalidOperand,
+        "fail to compile: invalid operand for a pointer.",
+        span,
+    ));
+}
+
+// controlling expressions are compared with zero, so any value but void works
+fn condition_of(checker: &mut Checker, node: &mut Node) {
+    expression(checker, node);
+    if node.ty() == &Type::Void {
+        void_value(checker, node);
+    }
+}
+
+// wrap `node` in a conversion to `ty` unless it has the type already
+fn convert(checker: &mut Checker, node: &mut Node, ty: Type) {
+    let from = node.ty();
+    if from == &ty {
+        return;
+    }
+    if from == &Type::Void {
+        void_value(checker, node);
+        return;
+    }
+    let span = node.span;
+    let inner = mem::replace(node, Node::new(Num(0), span));
+    *node = Node::new(Cast(Box::new(inner)), span);
+    node.ty = Some(ty);
+}
+
+// convert `node` to `ty` as if by assignment, where integers and pointers
+// don't mix except for the null pointer constant and a pointer only converts
+// to a pointer to the same type or from or to `void *`
+fn coerce(checker: &mut Checker, node: &mut Node, ty: Type) -> bool {
+    let from = node.ty().decay();
+    let message = match (from.base(), &ty) {
+        (Some(from), Type::Pointer(to))
+            if from != &**to && from != &Type::Void && **to != Type::Void =>
+        {
+            Some("incompatible pointer types")
+        }
+        (Some(_), _) if ty.is_integer() => Some("incompatible pointer to integer conversion"),
+        (None, Type::Pointer(_))
+            if from.is_integer() && evaluate(node, checker.strings) != Some((None, 0)) =>
+        {
+            Some("incompatible integer to pointer conversion")
+        }
+        _ => None,
+    };
+    if let Some(message) = message {
+        checker.errors.push(Diagnostic::error(
+            Code::Incompatible,
+            &format!("fail to compile: {}.", message),
+            node.span,
+        ));
+        return false;
+    }
+    convert(checker, node, ty);
+    true
+}
+
+fn void_value(checker: &mut Checker, node: &Node) {
+    checker.errors.push(Diagnostic::error(
+        Code::VoidValue,
+        "fail to compile: void value not ignored as it ought to be.",
+        node.span,
+    ));
+}
+
+#[cfg(test)]
+mod tests_type_check {
+    use super::*;
+    use crate::lexer::TokenStream;
+    use crate::parser::{parser, Node};
+
+    fn check(program: &str) -> Result<Program, Vec<Diagnostic>> {
+        let mut stream = TokenStream::tokenize(program.to_string()).unwrap();
+        let mut program = parser(&mut stream).unwrap();
+        check_types(&mut program)?;
+        Ok(program)
+    }
+
+    // type of the value of the last `return` or expression statement in
+    // `main`, a pointer can't be returned from it
+    fn returned(program: &Program) -> (&Type, &Node) {
+        let main = program.functions.iter().find(|f| f.name == "main").unwrap();
+        let value = match main.body.last().unwrap() {
+            Node {
+                kind: Return(Some(value)),
+                ..
+            } => value,
+            node => node,
+        };
+        match &value.kind {
+            Cast(inner) => (inner.ty(), inner),
+            _ => (value.ty(), value),
+        }
+    }
+
+    #[test]
+    fn for_conversion() {
+        let cases = [
+            ("char c; return c;", Type::Char),
+            ("char c; return c + c;", Type::Int),
+            ("short s; return -s;", Type::Int),
+            ("unsigned char c; return ~c;", Type::Int),
+            ("unsigned u; return u + 1;", Type::UnsignedInt),
+            ("long l; unsigned u; return l + u;", Type::Long),
+            ("unsigned long u; long l; return l * u;", Type::UnsignedLong),
+            ("int i; unsigned long u; return i < u;", Type::Int),
+            ("long l; return 1 << l;", Type::Int),
+            ("char c; return c <<= 1;", Type::Char),
+            ("long long l; return l;", Type::Long),
+            ("return 4294967296;", Type::Long),
+            ("return 1u;", Type::UnsignedInt),
+            ("return 'a';", Type::Int),
+            ("unsigned short s; char c; return 1 ? s : c;", Type::Int),
+            ("char c; return c++;", Type::Char),
+            ("long l; return l, 'a';", Type::Int),
+            ("char c; &c;", Type::pointer_to(Type::Char)),
+            ("int **p; *p;", Type::pointer_to(Type::Int)),
+            ("short *p; p + 1;", Type::pointer_to(Type::Short)),
+            ("short *p; 1 + p;", Type::pointer_to(Type::Short)),
+            ("long *p; long *q; return p - q;", Type::Long),
+            ("char *p; return p == 0;", Type::Int),
+            ("unsigned *p; p++;", Type::pointer_to(Type::UnsignedInt)),
+            ("int a[2][3]; a[1];", Type::array_of(Type::Int, 3)),
+            (
+                "int a[2][3]; a + 1;",
+                Type::pointer_to(Type::array_of(Type::Int, 3)),
+            ),
+            (
+                "int a[2][3]; &a;",
+                Type::pointer_to(Type::array_of(Type::array_of(Type::Int, 3), 2)),
+            ),
+            ("char a[2]; 0, a;", Type::pointer_to(Type::Char)),
+            ("return sizeof 1;", Type::UnsignedLong),
+        ];
+        for (case, answer) in cases.iter() {
+            let program = check(case).unwrap();
+            assert_eq!(returned(&program).0, answer, "{}", case);
+        }
+    }
+
+    #[test]
+    fn for_cast() {
+        // the operands are converted to the common type
+        let program = check("char c; long l; return c + l;").unwrap();
+        match &returned(&program).1.kind {
+            Add(left, right) => {
+                assert!(matches!(left.kind, Cast(_)));
+                assert_eq!(left.ty(), &Type::Long);
+                assert!(matches!(right.kind, LVar(_)));
+            }
+            kind => panic!("{:?}", kind),
+        }
+        // arguments take the types of the parameters
+        let program = check("char f(char c) { return c; } int main() { return f(300); }").unwrap();
+        match &returned(&program).1.kind {
+            Call { args, .. } => assert_eq!(args[0].ty(), &Type::Char),
+            kind => panic!("{:?}", kind),
+        }
+        assert_eq!(returned(&program).0, &Type::Char);
+    }
+
+    #[test]
+    fn for_pointer_arithmetic() {
+        // the integer is scaled by the size of the element
+        let program = check("int *p; 2 + p;").unwrap();
+        match &returned(&program).1.kind {
+            Add(left, right) => {
+                assert!(matches!(left.kind, LVar(_)));
+                match &right.kind {
+                    Mul(count, size) => {
+                        assert_eq!(count.ty(), &Type::Long);
+                        assert_eq!(size.kind, Num(4));
+                    }
+                    kind => panic!("{:?}", kind),
+                }
+            }
+            kind => panic!("{:?}", kind),
+        }
+        // the distance of two pointers is in elements
+        let program = check("long *p; long *q; return p - q;").unwrap();
+        match &returned(&program).1.kind {
+            Div(distance, size) => {
+                assert!(matches!(distance.kind, Sub(_, _)));
+                assert_eq!(size.kind, Num(8));
+            }
+            kind => panic!("{:?}", kind),
+        }
+        let cases = [
+            ("int *p; int *q; return p + q;", 25),
+            ("int *p; return 1 - p;", 17),
+            ("int *p; char *q; return p - q;", 26),
+            ("int *p; p * 2;", 8),
+            ("int *p; p *= 2;", 10),
+            ("int x; return *x;", 14),
+            ("void *p; return *p;", 16),
+        ];
+        for &(case, start) in cases.iter() {
+            let errors = check(case).unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", case);
+            assert_eq!(errors[0].code, Code::InvalidOperand);
+            assert_eq!(errors[0].span.start, start, "{}", case);
+        }
+    }
+
+    #[test]
+    fn for_sizeof() {
+        let cases = [
+            ("char c; return sizeof c;", 1),
+            ("return sizeof(1 + 1l);", 8),
+            ("char c; return sizeof(c + c);", 4),
+            ("int a[2][3]; return sizeof a;", 24),
+            ("int a[2][3]; return sizeof a[0];", 12),
+            ("int a[2][3]; return sizeof(a + 0);", 8),
+            ("int a[2][3]; return sizeof &a;", 8),
+            ("short *p; return sizeof *p;", 2),
+            ("return sizeof(sizeof 1);", 8),
+        ];
+        for &(case, answer) in cases.iter() {
+            let program = check(case).unwrap();
+            let (ty, node) = returned(&program);
+            assert_eq!(ty, &Type::UnsignedLong, "{}", case);
+            assert_eq!(evaluate(node, &[]), Some((None, answer)), "{}", case);
+        }
+        let errors = check("int a[2]; int b[2]; a = b;").unwrap_err();
+        assert_eq!(errors[0].code, Code::NotAssignable);
+        assert_eq!(errors[0].span.start, 20);
+    }
+
+    #[test]
+    fn for_void_value() {
+        let cases = [
+            ("void f() { 1; } int main() { return f(); }", 36),
+            ("void f() { 1; } int main() { int x = f(); }", 37),
+            ("void f() { 1; } int main() { if (f()) 1; }", 33),
+            ("void f() { 1; } int main() { return 1 + f(); }", 40),
+        ];
+        for &(case, start) in cases.iter() {
+            let errors = check(case).unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", case);
+            assert_eq!(errors[0].code, Code::VoidValue);
+            assert_eq!(errors[0].span.start, start, "{}", case);
+        }
+        // a void call is fine as a statement or a branch of a discarded `?:`
+        assert!(check("void f() { 1; } int main() { f(); 1 ? f() : f(); return 0; }").is_ok());
+    }
+
+    #[test]
+    fn for_return_value() {
+        let cases = [
+            ("void f() { return 1; } int main() { f(); return 0; }", 18),
+            ("int f() { return; } int main() { return f(); }", 10),
+        ];
+        for &(case, start) in cases.iter() {
+            let errors = check(case).unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", case);
+            assert_eq!(errors[0].code, Code::ReturnValue);
+            assert_eq!(errors[0].span.start, start, "{}", case);
+        }
+        assert!(check("void f(void) { return; } int main(void) { f(); return 0; }").is_ok());
+    }
+
+    #[test]
+    fn for_argument_count() {
+        let cases = [
+            (
+                "int f(int a) { return a; } int main() { return f(1, 2); }",
+                47,
+            ),
+            (
+                "int f(int a, int b) { return a; } int main() { return f(); }",
+                54,
+            ),
+        ];
+        for &(case, start) in cases.iter() {
+            let errors = check(case).unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", case);
+            assert_eq!(errors[0].code, Code::ArgumentCount);
+            assert_eq!(errors[0].span.start, start, "{}", case);
+        }
+        assert_eq!(
+            check("int f(int a) { return a; } int main() { return f(1, 2); }").unwrap_err()[0]
+                .message,
+            "fail to compile: `f` takes 1 argument but 2 were given."
+        );
+        // nothing is known about an undefined function
+        assert!(check("int main() { return g(1, 2, 3); }").is_ok());
+    }
+
+    #[test]
+    fn for_string() {
+        // equal literals share one entry
+        let program = check(
+            "char *s = \"ab\" + 1; int main() { char *t = \"cd\"; t = \"ab\"; return sizeof \"ab\"; }",
+        )
+        .unwrap();
+        assert_eq!(program.strings, vec![b"ab".to_vec(), b"cd".to_vec()]);
+        assert_eq!(program.globals[0].data, vec![Datum::Literal(0, 1)]);
+        assert_eq!(
+            evaluate(returned(&program).1, &program.strings),
+            Some((None, 3))
+        );
+        let program = check("char *t; t = \"x\"; return 0;").unwrap();
+        match &program.functions[0].body[1].kind {
+            Assign(_, value) => {
+                assert_eq!(value.ty(), &Type::pointer_to(Type::Char));
+                match &value.kind {
+                    Cast(literal) => assert_eq!(literal.ty(), &Type::array_of(Type::Char, 2)),
+                    kind => panic!("{:?}", kind),
+                }
+            }
+            kind => panic!("{:?}", kind),
+        }
+    }
+
+    #[test]
+    fn for_global_initializer() {
+        let program = check(
+            "char c = 300; unsigned u = -1; int a[3] = {1, 2 * 3}; int *p = &a[1] + 1; \
+             int n = sizeof a / sizeof a[0]; int main() { return 0; }",
+        )
+        .unwrap();
+        let data: Vec<_> = program.globals.iter().map(|g| g.data.clone()).collect();
+        assert_eq!(
+            data,
+            vec![
+                vec![Datum::Integer(1, 44)],
+                vec![Datum::Integer(4, 4294967295)],
+                vec![Datum::Integer(4, 1), Datum::Integer(4, 6), Datum::Zero(4)],
+                vec![Datum::Address("a".to_string(), 8)],
+                vec![Datum::Integer(4, 3)],
+            ]
+        );
+        let cases = [
+            ("int x = 1; int y = x; int main() { return 0; }", 19),
+            ("int a[1] = {1, 2}; int main() { return 0; }", 11),
+            ("int a[2] = 1; int main() { return 0; }", 11),
+            ("int x = 1 / 0; int main() { return 0; }", 10),
+        ];
+        for &(case, start) in cases.iter() {
+            let errors = check(case).unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", case);
+            assert_eq!(errors[0].code, Code::NotConstant);
+            assert_eq!(errors[0].span.start, start, "{}", case);
+        }
+    }
+
+    #[test]
+    fn for_incompatible() {
+        let cases = [
+            ("int *p; long l = p;", 17),
+            ("int *p; char *q = p;", 18),
+            ("int *p; p = 1;", 12),
+            (
+                "char f(char *s) { return *s; } int main() { int x; return f(&x); }",
+                60,
+            ),
+            ("int *f() { return 1; } int main() { f(); return 0; }", 18),
+            // the type is checked before the value
+            ("int x; char c = &x; int main() { return 0; }", 16),
+        ];
+        for &(case, start) in cases.iter() {
+            let errors = check(case).unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", case);
+            assert_eq!(errors[0].code, Code::Incompatible);
+            assert_eq!(errors[0].span.start, start, "{}", case);
+        }
+        // the null pointer constant and `void *` convert to any pointer
+        let cases = [
+            "int *p = 0; char *q = 1 - 1; void *v = p; q = v; return 0;",
+            "int x; void *v = &x; int main() { return 0; }",
+        ];
+        for case in cases.iter() {
+            assert!(check(case).is_ok(), "{}", case);
+        }
+    }
+}